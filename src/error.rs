@@ -0,0 +1,39 @@
+use std::{error, fmt::Display};
+
+use crate::{matcher::nfa::builder::BuildNFAError, parser::ParseRegexpError};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Parse(ParseRegexpError),
+    Build(BuildNFAError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(e) => write!(f, "{}", e),
+            Error::Build(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Parse(e) => Some(e),
+            Error::Build(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseRegexpError> for Error {
+    fn from(value: ParseRegexpError) -> Self {
+        Error::Parse(value)
+    }
+}
+
+impl From<BuildNFAError> for Error {
+    fn from(value: BuildNFAError) -> Self {
+        Error::Build(value)
+    }
+}
@@ -1,80 +1,540 @@
 /*
-char: [a-zA-z0-9\s\.]
-quantifier: [*, ?]
+char: [a-zA-z0-9\s]
+dot: [.]
+quantifier: [*, +, ?, {n}, {n,}, {n,m}]
 selector: [|]
+group: ["(", "(?:", "(?<" name ">", ")"]
+atomic: ["(?>"]
+lookaround: ["(?=", "(?!", "(?<=", "(?<!"]
+recursion: ["(?R)", "(?" [0-9]+ ")", "(?&" name ")"]
+conditional: ["(?(" [0-9]+ ")", "(?(<" name ">)"]
+callout: ["(?C)", "(?C" [0-9]+ ")"]
+flags: ["(?" [imsx]* ("-" [imsx]*)? ")", "(?" [imsx]* ("-" [imsx]*)? ":"]
+escape: ["\" punct, \n, \t, \r, \0, \xhh, \u{h..h}]
+quote: ["\Q" .. "\E", "\Q" .. end of pattern]
+backreference: ["\" [1-9][0-9]*, "\k<" name ">"]
+perl class: [\d, \D, \w, \W, \s, \S]
+class: ["[", "^", "-", "]", "&&", "--", "~~"]
+assertion: ["^", "$", "\A", "\z", "\b", "\B"]
+comment: ["(?#" .. ")", "#" .. "\n" in verbose mode]
 */
 
+use crate::parser::{Flags, ParseRegexpError, ParserResult};
+
 pub type Char = char;
 pub type Quantifier = char;
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Token {
     Char(char),
+    Dot,
     Quantifier(char),
+    // "{min,max}", max is None for "{min,}"
+    Repetition(usize, Option<usize>),
     Selector,
     Lparen,
+    NonCapture,
+    NamedGroup(String),
     Rparen,
+    // "(?>"
+    Atomic,
+    // "(?="
+    LookAhead,
+    // "(?!"
+    NegativeLookAhead,
+    // "(?<="
+    LookBehind,
+    // "(?<!"
+    NegativeLookBehind,
+    // "(?R)" or "(?1)", calls the group of the number, 0 for the whole pattern
+    Recurse(usize),
+    // "(?&name)"
+    NamedRecurse(String),
+    // "(?(1)", opens a group which matches its first alternative only if the group of the number has matched
+    Conditional(usize),
+    // "(?(<name>)"
+    NamedConditional(String),
+    // "(?C1)", 0 for "(?C)"
+    Callout(usize),
+    // "(?flags)", changes the flags until the end of the enclosing group
+    SetFlags(Flags),
+    // "(?flags:", opens a non-capturing group with the flags
+    ScopedFlags(Flags),
+    LBracket,
+    // "^" right after "["
+    Negation,
+    // "-" between two characters of a class
+    Hyphen,
+    // "&&", "--" or "~~" between two operands of a class, by its character
+    SetOp(char),
+    RBracket,
+    // "\1", the number of the group
+    Backref(usize),
+    // "\k<name>"
+    NamedBackref(String),
+    // "\d", "\w", "\s" or their uppercase negations, by the letter of the escape
+    PerlClass(char),
+    // zero-width assertion, "^" and "$" or the letter of its escape
+    Assert(char),
 }
 
 impl Token {
     pub fn to_char(&self) -> char {
         match *self {
-            Token::Char(c) | Token::Quantifier(c) => c,
+            Token::Char(c) | Token::Quantifier(c) | Token::PerlClass(c) | Token::Assert(c) | Token::SetOp(c) => c,
+            Token::Dot => '.',
+            Token::Repetition(_, _) => '{',
+            Token::Backref(_) | Token::NamedBackref(_) => '\\',
             Token::Selector => '|',
-            Token::Lparen => '(',
+            Token::Lparen | Token::NonCapture | Token::NamedGroup(_) | Token::SetFlags(_) | Token::ScopedFlags(_) => '(',
+            Token::Atomic | Token::LookAhead | Token::NegativeLookAhead | Token::LookBehind | Token::NegativeLookBehind => '(',
+            Token::Recurse(_) | Token::NamedRecurse(_) | Token::Conditional(_) | Token::NamedConditional(_) => '(',
+            Token::Callout(_) => '(',
             Token::Rparen => ')',
+            Token::LBracket => '[',
+            Token::Negation => '^',
+            Token::Hyphen => '-',
+            Token::RBracket => ']',
         }
     }
 }
 
 fn get_token(raw_token: char) -> Token {
     match raw_token {
-        c if c == '*' => Token::Quantifier(c),
-        c if c == '|' => Token::Selector,
-        c if c == '(' => Token::Lparen,
-        c if c == ')' => Token::Rparen,
+        '.' => Token::Dot,
+        '*' | '+' | '?' => Token::Quantifier(raw_token),
+        '|' => Token::Selector,
+        '(' => Token::Lparen,
+        ')' => Token::Rparen,
+        '[' => Token::LBracket,
+        '^' | '$' => Token::Assert(raw_token),
         c => Token::Char(c)
     }
 }
 
-pub fn get_tokens(raw: &str) -> Vec<Token> {
-    raw.chars()
-    .map(|ch| {
-        get_token(ch)
-    })
-    .collect()
+struct Lexer {
+    chars: Vec<char>,
+    idx: usize,
+    in_class: bool,
+    // right after "[" or "[^", where "]" and "-" are literal
+    class_start: bool,
+    // classes opened inside the current class and not closed yet
+    nested: usize,
+    // whitespace is skipped while the "x" flag is set
+    verbose: bool,
+    // the verbose flag outside each open group, restored by its ")"
+    groups: Vec<bool>,
+    // between "\Q" and "\E", where every character stands for itself
+    quoted: bool,
+}
+
+impl Lexer {
+    fn new(raw: &str, verbose: bool) -> Lexer {
+        Lexer {
+            chars: raw.chars().collect(),
+            idx: 0,
+            in_class: false,
+            class_start: false,
+            nested: 0,
+            verbose,
+            groups: vec![],
+            quoted: false,
+        }
+    }
+
+    fn next_token(&mut self) -> ParserResult<Option<Token>> {
+        if let Some(c) = self.quoted_char() {
+            self.class_start = false;
+            return Ok(Some(Token::Char(c)));
+        }
+        if self.in_class {
+            return self.class_token();
+        }
+        self.skip_comments()?;
+        let token = match self.bump() {
+            None => return Ok(None),
+            Some('\\') if self.peek() == Some('Q') => {
+                self.idx += 1;
+                self.quoted = true;
+                return self.next_token();
+            },
+            Some('(') if self.peek() == Some('?') => {
+                self.idx += 1;
+                self.group()?
+            },
+            Some('\\') => self.escape()?,
+            Some('{') => self.repetition()?,
+            Some(c) => get_token(c),
+        };
+        match token {
+            Token::LBracket => {
+                self.in_class = true;
+                self.class_start = true;
+            },
+            Token::Lparen | Token::NonCapture | Token::NamedGroup(_) | Token::Atomic => self.groups.push(self.verbose),
+            Token::Conditional(_) | Token::NamedConditional(_) => self.groups.push(self.verbose),
+            Token::LookAhead | Token::NegativeLookAhead | Token::LookBehind | Token::NegativeLookBehind => {
+                self.groups.push(self.verbose)
+            },
+            Token::ScopedFlags(flags) => {
+                self.groups.push(self.verbose);
+                self.verbose = flags.verbose.unwrap_or(self.verbose);
+            },
+            Token::SetFlags(flags) => self.verbose = flags.verbose.unwrap_or(self.verbose),
+            Token::Rparen => self.verbose = self.groups.pop().unwrap_or(self.verbose),
+            _ => (),
+        }
+        Ok(Some(token))
+    }
+
+    // the next character of a quoted span, None once "\E" or the end of the pattern closes it
+    fn quoted_char(&mut self) -> Option<char> {
+        if !self.quoted {
+            return None;
+        }
+        if self.chars[self.idx..].starts_with(&['\\', 'E']) {
+            self.idx += 2;
+            self.quoted = false;
+            return None;
+        }
+        let c = self.bump();
+        self.quoted = c.is_some();
+        c
+    }
+
+    // skips "(?#...)", and whitespace and "#" up to the end of the line in verbose mode
+    fn skip_comments(&mut self) -> ParserResult<()> {
+        loop {
+            match self.peek() {
+                Some(c) if self.verbose && c.is_whitespace() => self.idx += 1,
+                Some('#') if self.verbose => {
+                    while self.bump().is_some_and(|c| c != '\n') {}
+                },
+                Some('(') if self.chars[self.idx..].starts_with(&['(', '?', '#']) => {
+                    self.idx += 3;
+                    loop {
+                        match self.bump() {
+                            Some(')') => break,
+                            Some(_) => (),
+                            None => return Err(self.error(None)),
+                        }
+                    }
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // inside brackets every character but "\", "[", "]", "-" and doubled "&" or "~" stands for itself
+    fn class_token(&mut self) -> ParserResult<Option<Token>> {
+        let class_start = std::mem::replace(&mut self.class_start, false);
+        let token = match self.bump() {
+            None => return Ok(None),
+            Some('^') if class_start && self.chars[self.idx - 2] == '[' => {
+                self.class_start = true;
+                Token::Negation
+            },
+            Some('[') => {
+                self.nested += 1;
+                self.class_start = true;
+                Token::LBracket
+            },
+            Some(']') if !class_start && self.nested > 0 => {
+                self.nested -= 1;
+                Token::RBracket
+            },
+            Some(']') if !class_start => {
+                self.in_class = false;
+                Token::RBracket
+            },
+            Some(c @ ('&' | '-' | '~')) if !class_start && self.peek() == Some(c) => {
+                self.idx += 1;
+                Token::SetOp(c)
+            },
+            Some('-') if !class_start && self.peek() != Some(']') => Token::Hyphen,
+            Some('\\') if self.peek() == Some('Q') => {
+                self.idx += 1;
+                self.quoted = true;
+                self.class_start = class_start;
+                return self.next_token();
+            },
+            Some('\\') => self.escape()?,
+            Some(c) => Token::Char(c),
+        };
+        Ok(Some(token))
+    }
+
+    // "(?" has already been consumed
+    fn group(&mut self) -> ParserResult<Token> {
+        match self.bump() {
+            Some(':') => Ok(Token::NonCapture),
+            Some('>') => Ok(Token::Atomic),
+            Some('=') => Ok(Token::LookAhead),
+            Some('!') => Ok(Token::NegativeLookAhead),
+            Some('<') => {
+                match self.peek() {
+                    Some('=') => {
+                        self.idx += 1;
+                        Ok(Token::LookBehind)
+                    },
+                    Some('!') => {
+                        self.idx += 1;
+                        Ok(Token::NegativeLookBehind)
+                    },
+                    _ => Ok(Token::NamedGroup(self.name('>')?)),
+                }
+            },
+            Some('R') => match self.bump() {
+                Some(')') => Ok(Token::Recurse(0)),
+                c => Err(self.error(c)),
+            },
+            Some('0'..='9') => {
+                self.idx -= 1;
+                let index = self.number()?;
+                match self.bump() {
+                    Some(')') => Ok(Token::Recurse(index)),
+                    c => Err(self.error(c)),
+                }
+            },
+            Some('&') => Ok(Token::NamedRecurse(self.name(')')?)),
+            Some('(') => self.condition(),
+            Some('C') => {
+                let number = if self.peek() == Some(')') { 0 } else { self.number()? };
+                match self.bump() {
+                    Some(')') => Ok(Token::Callout(number)),
+                    c => Err(self.error(c)),
+                }
+            },
+            Some(_) => {
+                self.idx -= 1;
+                self.flags()
+            },
+            None => Err(self.error(None)),
+        }
+    }
+
+    // "(?(" has already been consumed
+    fn condition(&mut self) -> ParserResult<Token> {
+        let token = if self.peek() == Some('<') {
+            self.idx += 1;
+            Token::NamedConditional(self.name('>')?)
+        } else {
+            Token::Conditional(self.number()?)
+        };
+        match self.bump() {
+            Some(')') => Ok(token),
+            c => Err(self.error(c)),
+        }
+    }
+
+    // <flags> ::= [imsx]* ("-" [imsx]*)?, at least one letter, followed by ")" or ":"
+    fn flags(&mut self) -> ParserResult<Token> {
+        let mut flags = Flags::default();
+        let mut on = true;
+        let mut empty = true;
+        loop {
+            let flag = match self.bump() {
+                Some('-') if on => {
+                    on = false;
+                    continue;
+                },
+                Some(')') if !empty => return Ok(Token::SetFlags(flags)),
+                Some(':') if !empty => return Ok(Token::ScopedFlags(flags)),
+                Some('i') => &mut flags.case_insensitive,
+                Some('m') => &mut flags.multi_line,
+                Some('s') => &mut flags.dot_all,
+                Some('x') => &mut flags.verbose,
+                c => return Err(self.error(c)),
+            };
+            *flag = Some(on);
+            empty = false;
+        }
+    }
+
+    // "{" has already been consumed
+    fn repetition(&mut self) -> ParserResult<Token> {
+        let min = self.number()?;
+        let max = match self.bump() {
+            Some('}') => return Ok(Token::Repetition(min, Some(min))),
+            Some(',') if self.peek() == Some('}') => None,
+            Some(',') => Some(self.number()?),
+            c => return Err(self.error(c)),
+        };
+        match self.bump() {
+            Some('}') if max.is_none_or(|v| min <= v) => Ok(Token::Repetition(min, max)),
+            c => Err(self.error(c)),
+        }
+    }
+
+    fn number(&mut self) -> ParserResult<usize> {
+        let start = self.idx;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.idx += 1;
+        }
+        if start == self.idx {
+            let c = self.bump();
+            return Err(self.error(c));
+        }
+        // fails only when the number overflows
+        self.chars[start..self.idx]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .map_err(|_| ParseRegexpError::new(Some(Token::Char(self.chars[start])), start))
+    }
+
+    // "\" has already been consumed
+    fn escape(&mut self) -> ParserResult<Token> {
+        let c = match self.bump() {
+            Some(c @ ('A' | 'z' | 'b' | 'B')) if !self.in_class => return Ok(Token::Assert(c)),
+            Some(c @ ('d' | 'D' | 'w' | 'W' | 's' | 'S')) => return Ok(Token::PerlClass(c)),
+            Some('1'..='9') if !self.in_class => {
+                self.idx -= 1;
+                return Ok(Token::Backref(self.number()?));
+            },
+            Some('k') if !self.in_class => {
+                return match self.bump() {
+                    Some('<') => Ok(Token::NamedBackref(self.name('>')?)),
+                    c => Err(self.error(c)),
+                };
+            },
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('x') => self.code_point(false)?,
+            Some('u') => {
+                match self.bump() {
+                    Some('{') => self.code_point(true)?,
+                    c => return Err(self.error(c)),
+                }
+            },
+            // any punctuation stands for itself, so that metacharacters can be matched literally,
+            // and so does a space, which would be skipped in verbose mode
+            Some(c) if c.is_ascii_punctuation() || c == ' ' => c,
+            c => return Err(self.error(c)),
+        };
+        Ok(Token::Char(c))
+    }
+
+    // "\xhh" takes exactly two hex digits, "\u{h..h}" takes one to six digits closed by "}"
+    fn code_point(&mut self, braced: bool) -> ParserResult<char> {
+        let max_digits = if braced { 6 } else { 2 };
+        let mut code = 0u32;
+        let mut digits = 0;
+        while braced || digits < max_digits {
+            match self.bump() {
+                Some('}') if braced && digits > 0 => break,
+                Some(c) if c.is_ascii_hexdigit() && digits < max_digits => {
+                    code = code * 16 + c.to_digit(16).unwrap();
+                    digits += 1;
+                },
+                c => return Err(self.error(c)),
+            }
+        }
+        // surrogates and values beyond U+10FFFF are not characters
+        char::from_u32(code).ok_or_else(|| self.error(self.chars.get(self.idx - 1).copied()))
+    }
+
+    // <name> ::= [a-zA-Z_][a-zA-Z0-9_]*, followed by `close`
+    fn name(&mut self, close: char) -> ParserResult<String> {
+        let mut name = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == close && !name.is_empty() => return Ok(name),
+                Some(c) if c == '_' || c.is_ascii_alphabetic() => name.push(c),
+                Some(c) if c.is_ascii_digit() && !name.is_empty() => name.push(c),
+                c => return Err(self.error(c)),
+            }
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.idx += 1;
+        }
+        c
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.idx).copied()
+    }
+
+    // `cause` is the char just consumed, or None at the end of the pattern
+    fn error(&self, cause: Option<char>) -> ParseRegexpError {
+        match cause {
+            Some(c) => ParseRegexpError::new(Some(Token::Char(c)), self.idx - 1),
+            None => ParseRegexpError::new(None, self.idx),
+        }
+    }
+}
+
+// a pattern which matches exactly raw, with every character which has a meaning of its own escaped,
+// so that it stays literal even inside brackets or in verbose mode
+pub fn escape(raw: &str) -> String {
+    let mut escaped = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            '\\' | '.' | '*' | '+' | '?' | '|' | '(' | ')' | '[' | ']' | '{' | '}' | '^' | '$' | '-' | '&' | '~' | '#' | ' ' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c if c.is_whitespace() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn get_tokens(raw: &str) -> ParserResult<Vec<Token>> {
+    tokens(raw, false)
+}
+
+// the same as get_tokens, but as if the whole pattern were in the scope of "(?x)"
+pub fn get_verbose_tokens(raw: &str) -> ParserResult<Vec<Token>> {
+    tokens(raw, true)
+}
+
+fn tokens(raw: &str, verbose: bool) -> ParserResult<Vec<Token>> {
+    let mut lexer = Lexer::new(raw, verbose);
+    let mut tokens = vec![];
+    while let Some(token) = lexer.next_token()? {
+        tokens.push(token);
+    }
+    Ok(tokens)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::lexer::{get_tokens, Token};
+    use crate::{lexer::{escape, get_tokens, get_verbose_tokens, Token}, parser::{Flags, ParseRegexpError}};
 
     #[test]
     fn valid_string_only_with_literal_get_tokens() {
         let raw = "a";
         let expect = vec![Token::Char('a')];
         let actual = get_tokens(raw);
-        assert_eq!(expect, actual);
+        assert_eq!(Ok(expect), actual);
 
         let raw = "ab";
         let expect = vec![Token::Char('a'), Token::Char('b')];
         let actual = get_tokens(raw);
-        assert_eq!(expect, actual);
+        assert_eq!(Ok(expect), actual);
 
         let raw = "a*a";
         let expect = vec![Token::Char('a'), Token::Quantifier('*'), Token::Char('a')];
         let actual = get_tokens(raw);
-        assert_eq!(expect, actual);
+        assert_eq!(Ok(expect), actual);
 
         let raw = "a*";
         let expect = vec![Token::Char('a'), Token::Quantifier('*')];
         let actual = get_tokens(raw);
-        assert_eq!(expect, actual);
+        assert_eq!(Ok(expect), actual);
 
         let raw = "*a";
         let expect = vec![Token::Quantifier('*'), Token::Char('a')];
         let actual = get_tokens(raw);
-        assert_eq!(expect, actual);
+        assert_eq!(Ok(expect), actual);
 
         let raw = "(ab|c )*d*";
         let expect = vec![
@@ -90,7 +550,528 @@ mod test {
             Token::Quantifier('*')
         ];
         let actual = get_tokens(raw);
-        assert_eq!(expect, actual);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn group_get_tokens() {
+        let raw = "(?:a)(?<year>b)";
+        let expect = vec![
+            Token::NonCapture,
+            Token::Char('a'),
+            Token::Rparen,
+            Token::NamedGroup("year".to_string()),
+            Token::Char('b'),
+            Token::Rparen,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn backref_get_tokens() {
+        let raw = r"\1\12\k<name>\0";
+        let expect = vec![
+            Token::Backref(1),
+            Token::Backref(12),
+            Token::NamedBackref("name".to_string()),
+            Token::Char('\0'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let actual = get_tokens(r"\k{a}");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('{')), 2)), actual);
+
+        let actual = get_tokens(r"[\1]");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('1')), 2)), actual);
+    }
+
+    #[test]
+    fn lookaround_get_tokens() {
+        let raw = "(?>x)(?=a)(?!b)(?<=c)(?<!d)";
+        let expect = vec![
+            Token::Atomic,
+            Token::Char('x'),
+            Token::Rparen,
+            Token::LookAhead,
+            Token::Char('a'),
+            Token::Rparen,
+            Token::NegativeLookAhead,
+            Token::Char('b'),
+            Token::Rparen,
+            Token::LookBehind,
+            Token::Char('c'),
+            Token::Rparen,
+            Token::NegativeLookBehind,
+            Token::Char('d'),
+            Token::Rparen,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn recursion_get_tokens() {
+        let raw = "(?R)(?1)(?12)(?&name)";
+        let expect = vec![
+            Token::Recurse(0),
+            Token::Recurse(1),
+            Token::Recurse(12),
+            Token::NamedRecurse("name".to_string()),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let actual = get_tokens("(?Ra)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('a')), 3)), actual);
+
+        let actual = get_tokens("(?1a)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('a')), 3)), actual);
+
+        let actual = get_tokens("(?&)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char(')')), 3)), actual);
+    }
+
+    #[test]
+    fn conditional_get_tokens() {
+        let raw = "(?(1)a|b)(?(<name>)c)";
+        let expect = vec![
+            Token::Conditional(1),
+            Token::Char('a'),
+            Token::Selector,
+            Token::Char('b'),
+            Token::Rparen,
+            Token::NamedConditional("name".to_string()),
+            Token::Char('c'),
+            Token::Rparen,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let actual = get_tokens("(?(a)b)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('a')), 3)), actual);
+
+        let actual = get_tokens("(?(<x)b)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char(')')), 5)), actual);
+
+        let actual = get_tokens("(?(1>b)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('>')), 4)), actual);
+    }
+
+    #[test]
+    fn callout_get_tokens() {
+        let raw = "(?C)a(?C12)";
+        let expect = vec![Token::Callout(0), Token::Char('a'), Token::Callout(12)];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let actual = get_tokens("(?Ca)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('a')), 3)), actual);
+
+        let actual = get_tokens("(?C1");
+        assert_eq!(Err(ParseRegexpError::new(None, 4)), actual);
+    }
+
+    #[test]
+    fn flags_get_tokens() {
+        let raw = "a(?i)b";
+        let expect = vec![
+            Token::Char('a'),
+            Token::SetFlags(Flags { case_insensitive: Some(true), ..Flags::default() }),
+            Token::Char('b'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let raw = "(?im-sx)(?s:.)";
+        let expect = vec![
+            Token::SetFlags(Flags {
+                case_insensitive: Some(true),
+                multi_line: Some(true),
+                dot_all: Some(false),
+                verbose: Some(false),
+            }),
+            Token::ScopedFlags(Flags { dot_all: Some(true), ..Flags::default() }),
+            Token::Dot,
+            Token::Rparen,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let actual = get_tokens("(?ia)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('a')), 3)), actual);
+
+        let actual = get_tokens("(?-)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char(')')), 3)), actual);
+
+        let actual = get_tokens("(?i-m-s)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('-')), 5)), actual);
+    }
+
+    #[test]
+    fn verbose_get_tokens() {
+        // whitespace is skipped only in the scope of "x", except inside brackets or escaped
+        let raw = "(?x: a b* [ c]\\ d) e";
+        let expect = vec![
+            Token::ScopedFlags(Flags { verbose: Some(true), ..Flags::default() }),
+            Token::Char('a'),
+            Token::Char('b'),
+            Token::Quantifier('*'),
+            Token::LBracket,
+            Token::Char(' '),
+            Token::Char('c'),
+            Token::RBracket,
+            Token::Char(' '),
+            Token::Char('d'),
+            Token::Rparen,
+            Token::Char(' '),
+            Token::Char('e'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let raw = "a(?x)( b)  c";
+        let expect = vec![
+            Token::Char('a'),
+            Token::SetFlags(Flags { verbose: Some(true), ..Flags::default() }),
+            Token::Lparen,
+            Token::Char('b'),
+            Token::Rparen,
+            Token::Char('c'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn comment_get_tokens() {
+        let raw = "a(?#any (text)*b";
+        let expect = vec![Token::Char('a'), Token::Quantifier('*'), Token::Char('b')];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let raw = "
+            \\d+   # digits
+            [#]\\# # a literal sharp
+            (?#inline) $
+        ";
+        let expect = vec![
+            Token::PerlClass('d'),
+            Token::Quantifier('+'),
+            Token::LBracket,
+            Token::Char('#'),
+            Token::RBracket,
+            Token::Char('#'),
+            Token::Assert('$'),
+        ];
+        let actual = get_verbose_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        // "#" is literal out of verbose mode
+        let actual = get_tokens("a#b");
+        assert_eq!(Ok(vec![Token::Char('a'), Token::Char('#'), Token::Char('b')]), actual);
+
+        let actual = get_tokens("a(?#b");
+        assert_eq!(Err(ParseRegexpError::new(None, 5)), actual);
+    }
+
+    #[test]
+    fn invalid_group_get_tokens() {
+        let actual = get_tokens("(?");
+        assert_eq!(Err(ParseRegexpError::new(None, 2)), actual);
+
+        let actual = get_tokens("(?a)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('a')), 2)), actual);
+
+        let actual = get_tokens("(?<>a)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('>')), 3)), actual);
+
+        let actual = get_tokens("(?<1a>a)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('1')), 3)), actual);
+
+        let actual = get_tokens("(?<a-b>a)");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('-')), 4)), actual);
+
+        let actual = get_tokens("(?<ab");
+        assert_eq!(Err(ParseRegexpError::new(None, 5)), actual);
+    }
+
+    #[test]
+    fn escape_get_tokens() {
+        let raw = r"\*\(\|\)\\\.";
+        let expect = "*(|)\\.".chars().map(Token::Char).collect::<Vec<Token>>();
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let raw = r"a.\n\t\r\0";
+        let expect = vec![
+            Token::Char('a'),
+            Token::Dot,
+            Token::Char('\n'),
+            Token::Char('\t'),
+            Token::Char('\r'),
+            Token::Char('\0'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let raw = r"\x41\x7e\u{1F600}\u{e9}";
+        let expect = vec![
+            Token::Char('A'),
+            Token::Char('~'),
+            Token::Char('😀'),
+            Token::Char('é'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn quote_get_tokens() {
+        let raw = r"a\Q*(|\\E+\Qb";
+        let expect = vec![
+            Token::Char('a'),
+            Token::Char('*'),
+            Token::Char('('),
+            Token::Char('|'),
+            Token::Char('\\'),
+            Token::Quantifier('+'),
+            Token::Char('b'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        // quoted characters are literal inside brackets and in verbose mode as well
+        let raw = r"[\Q^]\E-] \Q# c\E";
+        let expect = vec![
+            Token::LBracket,
+            Token::Char('^'),
+            Token::Char(']'),
+            Token::Char('-'),
+            Token::RBracket,
+            Token::Char('#'),
+            Token::Char(' '),
+            Token::Char('c'),
+        ];
+        let actual = get_verbose_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn escape_str() {
+        assert_eq!(escape("a.b*c"), r"a\.b\*c");
+        assert_eq!(escape("(x|y)"), r"\(x\|y\)");
+        assert_eq!(escape("[^a-z]{2}$"), r"\[\^a\-z\]\{2\}\$");
+        assert_eq!(escape("# a\tb"), "\\#\\ a\\u{9}b");
+        assert_eq!(escape("日本"), "日本");
+    }
+
+    #[test]
+    fn invalid_escape_get_tokens() {
+        let actual = get_tokens("\\");
+        assert_eq!(Err(ParseRegexpError::new(None, 1)), actual);
+
+        let actual = get_tokens("ab\\");
+        assert_eq!(Err(ParseRegexpError::new(None, 3)), actual);
+
+        let actual = get_tokens(r"\q");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('q')), 1)), actual);
+
+        let actual = get_tokens(r"\x4");
+        assert_eq!(Err(ParseRegexpError::new(None, 3)), actual);
+
+        let actual = get_tokens(r"\x4g");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('g')), 3)), actual);
+
+        let actual = get_tokens(r"\u41");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('4')), 2)), actual);
+
+        let actual = get_tokens(r"\u{}");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('}')), 3)), actual);
+
+        let actual = get_tokens(r"\u{1234567}");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('7')), 9)), actual);
+
+        // surrogate
+        let actual = get_tokens(r"\u{D800}");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('}')), 7)), actual);
+    }
+
+    #[test]
+    fn class_get_tokens() {
+        let raw = "[a-z0-9_]*";
+        let expect = vec![
+            Token::LBracket,
+            Token::Char('a'),
+            Token::Hyphen,
+            Token::Char('z'),
+            Token::Char('0'),
+            Token::Hyphen,
+            Token::Char('9'),
+            Token::Char('_'),
+            Token::RBracket,
+            Token::Quantifier('*'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        // metacharacters, "]" and "-" at the edges and "^" not at the start are literal
+        let raw = r"[^]*.(|^-]a[-\]\n-]";
+        let expect = vec![
+            Token::LBracket,
+            Token::Negation,
+            Token::Char(']'),
+            Token::Char('*'),
+            Token::Char('.'),
+            Token::Char('('),
+            Token::Char('|'),
+            Token::Char('^'),
+            Token::Char('-'),
+            Token::RBracket,
+            Token::Char('a'),
+            Token::LBracket,
+            Token::Char('-'),
+            Token::Char(']'),
+            Token::Char('\n'),
+            Token::Char('-'),
+            Token::RBracket,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn class_set_get_tokens() {
+        let raw = "[a-z--[^aeiou]&&\\w~~[x]]";
+        let expect = vec![
+            Token::LBracket,
+            Token::Char('a'),
+            Token::Hyphen,
+            Token::Char('z'),
+            Token::SetOp('-'),
+            Token::LBracket,
+            Token::Negation,
+            Token::Char('a'),
+            Token::Char('e'),
+            Token::Char('i'),
+            Token::Char('o'),
+            Token::Char('u'),
+            Token::RBracket,
+            Token::SetOp('&'),
+            Token::PerlClass('w'),
+            Token::SetOp('~'),
+            Token::LBracket,
+            Token::Char('x'),
+            Token::RBracket,
+            Token::RBracket,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        // a single "&" or "~" is literal, and so is "]" right after a nested "["
+        let raw = "[&~[]]]";
+        let expect = vec![
+            Token::LBracket,
+            Token::Char('&'),
+            Token::Char('~'),
+            Token::LBracket,
+            Token::Char(']'),
+            Token::RBracket,
+            Token::RBracket,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn quantifier_get_tokens() {
+        let raw = "a+b?c*?";
+        let expect = vec![
+            Token::Char('a'),
+            Token::Quantifier('+'),
+            Token::Char('b'),
+            Token::Quantifier('?'),
+            Token::Char('c'),
+            Token::Quantifier('*'),
+            Token::Quantifier('?'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn perl_class_get_tokens() {
+        let raw = r"\d\D\w[\W\s.]\S";
+        let expect = vec![
+            Token::PerlClass('d'),
+            Token::PerlClass('D'),
+            Token::PerlClass('w'),
+            Token::LBracket,
+            Token::PerlClass('W'),
+            Token::PerlClass('s'),
+            Token::Char('.'),
+            Token::RBracket,
+            Token::PerlClass('S'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn assertion_get_tokens() {
+        let raw = r"^\Aa\b\B$\z[$]";
+        let expect = vec![
+            Token::Assert('^'),
+            Token::Assert('A'),
+            Token::Char('a'),
+            Token::Assert('b'),
+            Token::Assert('B'),
+            Token::Assert('$'),
+            Token::Assert('z'),
+            Token::LBracket,
+            Token::Char('$'),
+            Token::RBracket,
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+
+        let actual = get_tokens(r"[\A]");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('A')), 2)), actual);
+    }
+
+    #[test]
+    fn repetition_get_tokens() {
+        let raw = "a{3}b{2,}c{2,5}?";
+        let expect = vec![
+            Token::Char('a'),
+            Token::Repetition(3, Some(3)),
+            Token::Char('b'),
+            Token::Repetition(2, None),
+            Token::Char('c'),
+            Token::Repetition(2, Some(5)),
+            Token::Quantifier('?'),
+        ];
+        let actual = get_tokens(raw);
+        assert_eq!(Ok(expect), actual);
+    }
+
+    #[test]
+    fn invalid_repetition_get_tokens() {
+        let actual = get_tokens("a{");
+        assert_eq!(Err(ParseRegexpError::new(None, 2)), actual);
+
+        let actual = get_tokens("a{b}");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('b')), 2)), actual);
+
+        let actual = get_tokens("a{3,1}");
+        assert_eq!(Err(ParseRegexpError::new(Some(Token::Char('}')), 5)), actual);
+
+        let actual = get_tokens("a{2,3");
+        assert_eq!(Err(ParseRegexpError::new(None, 5)), actual);
+
+        let actual = get_tokens("a{99999999999999999999999}");
+        assert!(actual.is_err());
+    }
+}
+
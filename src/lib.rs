@@ -2,7 +2,11 @@ pub mod lexer;
 pub mod parser;
 pub mod matcher;
 pub mod utils;
+pub mod error;
+pub mod regex;
+
+pub use crate::{error::Error, lexer::escape, regex::{Regex, RegexBuilder}};
 
 #[cfg(test)]
 mod tests {
-}
\ No newline at end of file
+}
@@ -1,7 +1,85 @@
 #[macro_use]
 pub mod nfa;
 pub mod executor;
+pub mod matches;
+pub mod captures;
+pub mod callout;
+
+use std::sync::Arc;
+
+pub use matches::{Match, Matches};
+pub use captures::Captures;
+pub use callout::{CalloutInfo, CalloutResult};
 
 pub trait Matcher {
-    fn exec(&self, input: & str) -> Vec<String>;
-}
\ No newline at end of file
+    // tries to match only at `start`, i.e. the match must begin there,
+    // and must also finish at `end` when it is given.
+    // slots[2 * i] and slots[2 * i + 1] are the span of the i-th capture group
+    fn slots_at(&self, haystack: &str, start: usize, end: Option<usize>) -> Option<Vec<Option<usize>>>;
+
+    // names of the capture groups, indexed by group number
+    fn capture_names(&self) -> Arc<[Option<String>]>;
+
+    // whether searches only try the position they start at instead of scanning forward
+    fn is_anchored(&self) -> bool {
+        false
+    }
+
+    // the positions a search starting at `start` tries, in order.
+    // positions are byte offsets, those in the middle of a character are skipped
+    fn starts(&self, haystack: &str, start: usize) -> impl Iterator<Item = usize> {
+        let last = if self.is_anchored() { start } else { haystack.len() };
+        (start..=last).filter(|&v| haystack.is_char_boundary(v))
+    }
+
+    fn match_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        let slots = self.slots_at(haystack, start, None)?;
+        Some(Match::new(haystack, slots[0]?, slots[1]?))
+    }
+
+    // a match which spans the whole haystack
+    fn full_match<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        let slots = self.slots_at(haystack, 0, Some(haystack.len()))?;
+        Some(Match::new(haystack, slots[0]?, slots[1]?))
+    }
+
+    // leftmost match which begins at or after `start`
+    fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        self.starts(haystack, start).find_map(|begin| self.match_at(haystack, begin))
+    }
+
+    fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.find_at(haystack, 0)
+    }
+
+    fn is_match(&self, haystack: &str) -> bool {
+        self.find(haystack).is_some()
+    }
+
+    fn find_iter<'m, 'h>(&'m self, haystack: &'h str) -> Matches<'m, 'h, Self>
+    where
+        Self: Sized,
+    {
+        Matches::new(self, haystack)
+    }
+
+    // same as find_at, but also reports where each capture group matched
+    fn captures_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Captures<'h>> {
+        self.starts(haystack, start)
+            .find_map(|begin| self.slots_at(haystack, begin, None))
+            .map(|slots| Captures::new(haystack, slots, self.capture_names()))
+    }
+
+    fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        self.captures_at(haystack, 0)
+    }
+
+    fn exec(&self, input: &str) -> Vec<String>
+    where
+        Self: Sized,
+    {
+        self.find_iter(input)
+            .map(|m| m.as_str().to_string())
+            .collect()
+    }
+}
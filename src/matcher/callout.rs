@@ -0,0 +1,20 @@
+// what a callout sees when the match reaches "(?C<number>)"
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CalloutInfo<'a> {
+    // the number written in the pattern, 0 for "(?C)"
+    pub number: usize,
+    // byte offset into the haystack where the callout is reached
+    pub position: usize,
+    // capture slots as far as the match has got, laid out as in Matcher::slots_at
+    pub slots: &'a [Option<usize>],
+}
+
+// how the match goes on after a callout
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CalloutResult {
+    Continue,
+    // this way fails, and the match backtracks as if the callout were a mismatch
+    Fail,
+    // the whole search fails at once, no other way nor start position is tried
+    Abort,
+}
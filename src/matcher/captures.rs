@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use super::Match;
+
+// spans of every capture group of a single match, group 0 being the whole match
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>,
+    names: Arc<[Option<String>]>,
+}
+
+impl<'h> Captures<'h> {
+    pub fn new(haystack: &'h str, slots: Vec<Option<usize>>, names: Arc<[Option<String>]>) -> Captures<'h> {
+        Captures { haystack, slots, names }
+    }
+
+    // None if the group did not take part in the match
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        let start = (*self.slots.get(i * 2)?)?;
+        let end = (*self.slots.get(i * 2 + 1)?)?;
+        Some(Match::new(self.haystack, start, end))
+    }
+
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        let i = self.names
+            .iter()
+            .position(|v| v.as_deref() == Some(name))?;
+        self.get(i)
+    }
+
+    // the number of groups, including the implicit group 0
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn iter<'c>(&'c self) -> impl Iterator<Item = Option<Match<'h>>> + 'c {
+        (0..self.len()).map(|i| self.get(i))
+    }
+}
@@ -1,83 +1,459 @@
-use crate::matcher::nfa::{Node, State};
+use std::{collections::HashMap, ops::ControlFlow, sync::Arc};
 
-use super::super::{nfa::NFA, Matcher};
+use crate::matcher::nfa::{Lookaround, Node, State, Trigger};
+use crate::parser::{class::simple_fold, Assertion};
+use crate::utils::list::List;
+
+use super::super::{nfa::NFA, CalloutInfo, CalloutResult, Matcher};
+
+pub const DEFAULT_RECURSION_LIMIT: usize = 250;
 
 pub struct BackTracer{
     nfa: NFA,
+    // outgoing transitions of each state
+    transitions: HashMap<State, Vec<(Trigger, List<Node>)>>,
+    captures: Arc<[Option<String>]>,
+    // the state where the body of each group begins, which a call enters
+    groups: HashMap<usize, State>,
+    // upper bound of the number of calls in progress, a call beyond it fails
+    recursion_limit: usize,
+}
+
+// a call in progress
+struct Frame {
+    group: usize,
+    // where the match goes on once the group is closed
+    ret: State,
+    // the captures at the call, which the group gets back when it returns
+    slots: Vec<Option<usize>>,
+}
+
+// a state on the current path, with its transitions left to try and how to undo the one being tried
+struct Visit {
+    idx: usize,
+    candidates: std::vec::IntoIter<(bool, Node, Trigger)>,
+    saved: Option<[(usize, Option<usize>); 2]>,
+    looked: Option<Vec<Option<usize>>>,
+}
+
+// what the search goes back to once the way it took fails
+enum Step {
+    Visit(Visit),
+    // a call was made, the start slot of the group held old before it
+    Call { start: usize, old: Option<usize> },
+    // a call returned, inner are the captures made inside it
+    Ret { inner: Vec<Option<usize>>, frame: Frame },
+}
+
+// per-search state of the matching, every position is a byte offset into input
+struct Context<'a> {
+    input: &'a str,
+    // the state which completes the match, the accepted state of a sub-automaton in a lookaround
+    goal: State,
+    // where the match has to end, anywhere if None
+    end: Option<usize>,
+    // input is read from right to left inside a lookbehind
+    backward: bool,
+    // the span of each group its last time through, followed by where each open group was entered.
+    // a group's span changes only when it is closed, so a backreference inside it sees the former one
+    slots: Vec<Option<usize>>,
+    // states on the current path, with the position and the call depth each was entered at
+    trail: Vec<(State, usize, usize)>,
+    // calls in progress, the innermost last
+    calls: Vec<Frame>,
+    // called at each callout, which always lets the match go on without it
+    callout: Option<&'a mut dyn FnMut(CalloutInfo) -> CalloutResult>,
+    // set once the callout aborts the search
+    aborted: bool,
+}
+
+impl<'a> Context<'a> {
+    fn new(input: &'a str, goal: State, end: Option<usize>, captures: usize) -> Context<'a> {
+        Context {
+            input,
+            goal,
+            end,
+            backward: false,
+            slots: vec![None; captures * 3],
+            trail: vec![],
+            calls: vec![],
+            callout: None,
+            aborted: false,
+        }
+    }
+
+    // the character which a transition at idx consumes
+    fn next_char(&self, idx: usize) -> Option<char> {
+        if self.backward {
+            self.input[..idx].chars().next_back()
+        } else {
+            self.input[idx..].chars().next()
+        }
+    }
+
+    // the position after consuming the text captured by the group at idx,
+    // None if the group has not matched or the input differs there
+    fn backref(&self, group: usize, caseless: bool, idx: usize) -> Option<usize> {
+        let captured = &self.input[self.slots[group * 2]?..self.slots[group * 2 + 1]?];
+        let eq = |a: char, b: char| if caseless { simple_fold(a) == simple_fold(b) } else { a == b };
+        let mut len = 0;
+        if self.backward {
+            let mut rest = self.input[..idx].chars().rev();
+            for b in captured.chars().rev() {
+                len += rest.next().filter(|&a| eq(a, b))?.len_utf8();
+            }
+            Some(idx - len)
+        } else {
+            let mut rest = self.input[idx..].chars();
+            for b in captured.chars() {
+                len += rest.next().filter(|&a| eq(a, b))?.len_utf8();
+            }
+            Some(idx + len)
+        }
+    }
+
+    // idx if the callout lets the match go on from there
+    fn callout(&mut self, number: usize, idx: usize) -> Option<usize> {
+        let Some(callout) = self.callout.as_mut() else {
+            return Some(idx);
+        };
+        let slots = &self.slots[..self.slots.len() / 3 * 2];
+        match callout(CalloutInfo { number, position: idx, slots }) {
+            CalloutResult::Continue => Some(idx),
+            CalloutResult::Fail => None,
+            CalloutResult::Abort => {
+                self.aborted = true;
+                None
+            },
+        }
+    }
+
+    fn captures(&self) -> usize {
+        self.slots.len() / 3
+    }
+
+    // the spans of the groups, which the search reports
+    fn spans(&self) -> &[Option<usize>] {
+        &self.slots[..self.captures() * 2]
+    }
+
+    // where the group was entered while it is open
+    fn start_slot(&self, group: usize) -> usize {
+        self.captures() * 2 + group
+    }
+
+    // records idx for the save, and returns the old values of the slots it changes
+    fn save(&mut self, slot: usize, idx: usize) -> [(usize, Option<usize>); 2] {
+        let start = self.start_slot(slot / 2);
+        // a reversed automaton enters a group by the save of its end
+        if slot.is_multiple_of(2) != self.backward {
+            let old = self.slots[start].replace(idx);
+            return [(start, old), (start, old)];
+        }
+        let entered = self.slots[start];
+        let other = slot ^ 1;
+        [
+            (other, std::mem::replace(&mut self.slots[other], entered)),
+            (slot, self.slots[slot].replace(idx)),
+        ]
+    }
+
+    fn has_matched(&self, group: usize) -> bool {
+        self.slots[group * 2].is_some() && self.slots[group * 2 + 1].is_some()
+    }
+
+    // the position after consuming the character at idx
+    fn step(&self, idx: usize) -> usize {
+        let len = self.next_char(idx).map_or(0, char::len_utf8);
+        if self.backward {
+            idx - len
+        } else {
+            idx + len
+        }
+    }
 }
 
 impl BackTracer {
     pub fn new(nfa: NFA) -> BackTracer {
+        let mut transitions = HashMap::<State, Vec<(Trigger, List<Node>)>>::new();
+        for ((state, trigger), nodes) in nfa.get_transition_table() {
+            transitions.entry(*state)
+                .or_default()
+                .push((trigger.clone(), nodes.clone()));
+        }
+        // a group is entered by the save of its start, copies of a repeated group are all the same
+        let groups = nfa.get_transition_table()
+            .iter()
+            .filter_map(|((_, trigger), nodes)| match trigger {
+                Trigger::Save(slot) if slot % 2 == 0 => Some((slot / 2, nodes.iter().next()?.state())),
+                _ => None,
+            })
+            .collect();
+        let captures = nfa.get_captures().into();
         BackTracer {
             nfa,
+            transitions,
+            captures,
+            groups,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
         }
     }
 
-    fn match_dfs(&self, input: &str, cur: State, idx: usize) -> Option<usize> {
-        if self.is_accepted(cur) {
-            return Some(idx);
+    pub fn recursion_limit(mut self, limit: usize) -> BackTracer {
+        self.recursion_limit = limit;
+        self
+    }
+
+    // the same as slots_at, but calls callout at every "(?C)" the match goes through.
+    // breaks when callout aborts, and then no other start position should be tried
+    pub fn slots_with_callout(
+        &self,
+        haystack: &str,
+        start: usize,
+        end: Option<usize>,
+        callout: &mut dyn FnMut(CalloutInfo) -> CalloutResult,
+    ) -> ControlFlow<(), Option<Vec<Option<usize>>>> {
+        if !haystack.is_char_boundary(start) {
+            return ControlFlow::Continue(None);
         }
+        let mut ctx = Context::new(haystack, self.nfa.get_accepted_state(), end, self.captures.len());
+        ctx.callout = Some(callout);
+        let matched = self.match_dfs(&mut ctx, self.nfa.get_init_state(), start);
+        if ctx.aborted {
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(matched.map(|_| ctx.spans().to_vec()))
+    }
 
-        let mut candidates = self.get_next_states(cur, None);
-        if let Some(trigger) = input.chars().nth(idx) {
-            candidates = candidates.into_iter()
-            .chain(self.get_next_states(cur, Some(trigger)).into_iter())
-            .collect::<Vec<(bool, Node)>>()
-        };
-        candidates.sort();
+    // searches depth first with a stack of its own, so that a long input cannot overflow the call stack.
+    // only a lookaround or an atomic group runs a nested search, which is as deep as the pattern nests
+    fn match_dfs(&self, ctx: &mut Context, init: State, idx: usize) -> Option<usize> {
+        let base = ctx.trail.len();
+        let mut stack = Vec::new();
+        let mut next = Some((init, idx));
+        while !ctx.aborted {
+            if let Some((cur, idx)) = next {
+                if cur == ctx.goal && ctx.end.is_none_or(|end| end == idx) {
+                    ctx.trail.truncate(base);
+                    return Some(idx);
+                }
+                // coming back to a state without consuming anything can only loop forever
+                let depth = ctx.calls.len();
+                if !ctx.trail.iter().rev().take_while(|v| v.1 == idx).any(|v| v.0 == cur && v.2 == depth) {
+                    let mut candidates = self.get_next_states(ctx, cur, idx);
+                    candidates.sort_by_key(|v| (v.0, v.1));
+                    ctx.trail.push((cur, idx, depth));
+                    let visit = Visit { idx, candidates: candidates.into_iter(), saved: None, looked: None };
+                    next = self.follow(ctx, &mut stack, visit);
+                    continue;
+                }
+            }
+            // the way failed, so the latest step is undone and its next way is tried
+            next = match stack.pop() {
+                None => break,
+                Some(Step::Call { start, old }) => {
+                    ctx.slots[start] = old;
+                    ctx.calls.pop();
+                    None
+                },
+                Some(Step::Ret { inner, frame }) => {
+                    ctx.slots = inner;
+                    ctx.calls.push(frame);
+                    None
+                },
+                Some(Step::Visit(mut visit)) => {
+                    // captures inside a lookaround or an atomic group are kept only as long as the match goes on from it
+                    if let Some(slots) = visit.looked.take() {
+                        ctx.slots = slots;
+                    }
+                    for (slot, old) in visit.saved.take().into_iter().flatten().rev() {
+                        ctx.slots[slot] = old;
+                    }
+                    self.follow(ctx, &mut stack, visit)
+                },
+            };
+        }
+        ctx.trail.truncate(base);
+        None
+    }
 
-        for i in candidates.into_iter() {
-            if let Some(v) = self.match_dfs(input,
-                i.1.state(),
-                idx + if i.0 {1} else {0}) {
-                return Some(v);
+    // takes the transitions of the visit in order until one can be taken, and returns the state and the position it leads to.
+    // the visit is left on the stack with how to undo it, followed by the step of a call or a return
+    fn follow(&self, ctx: &mut Context, stack: &mut Vec<Step>, mut visit: Visit) -> Option<(State, usize)> {
+        let idx = visit.idx;
+        while let Some((is_consumed, node, trigger)) = visit.candidates.next() {
+            // a call goes on by itself from where the group returns to
+            let called = match trigger {
+                Trigger::Call(group) => Some(self.call(ctx, group, node.state(), idx)),
+                Trigger::Save(slot) if ctx.calls.last().is_some_and(|v| v.group * 2 + 1 == slot) => Some(self.ret(ctx)),
+                _ => None,
+            };
+            if let Some(called) = called {
+                let Some((step, state)) = called else {
+                    continue;
+                };
+                stack.push(Step::Visit(visit));
+                stack.push(step);
+                return Some((state, idx));
             }
-        };
+            visit.saved = match trigger {
+                Trigger::Save(slot) => Some(ctx.save(slot, idx)),
+                _ => None,
+            };
+            visit.looked = match trigger {
+                Trigger::Look(_) | Trigger::Atomic(_, _) => Some(ctx.slots.clone()),
+                _ => None,
+            };
+            let next = match trigger {
+                Trigger::Look(look) => self.look_around(ctx, look, idx),
+                Trigger::Atomic(init, accepted) => self.atomic(ctx, init, accepted, idx),
+                Trigger::Backref(group, caseless) => ctx.backref(group, caseless, idx),
+                Trigger::Callout(number) => ctx.callout(number, idx),
+                _ if is_consumed => Some(ctx.step(idx)),
+                _ => Some(idx),
+            };
+            let Some(next) = next else {
+                continue;
+            };
+            stack.push(Step::Visit(visit));
+            return Some((node.state(), next));
+        }
+        // no way from the state is left
+        ctx.trail.pop();
+        None
+    }
 
-        return None;
+    // runs the sub-automaton from idx, the first way it matches is taken and never backtracked into.
+    // a lookbehind runs a reversed sub-automaton leftward, so it is not bounded in length.
+    // returns idx if the lookaround holds, as it consumes nothing
+    fn look_around(&self, ctx: &mut Context, look: Lookaround, idx: usize) -> Option<usize> {
+        // a negative lookaround never keeps what it captured
+        let slots = look.negated.then(|| ctx.slots.clone());
+        let goal = std::mem::replace(&mut ctx.goal, look.accepted_state);
+        let end = ctx.end.take();
+        let backward = std::mem::replace(&mut ctx.backward, look.behind);
+        let matched = self.match_dfs(ctx, look.init_state, idx).is_some();
+        ctx.goal = goal;
+        ctx.end = end;
+        ctx.backward = backward;
+        if let Some(slots) = slots {
+            ctx.slots = slots;
+        }
+        (matched != look.negated).then_some(idx)
+    }
+
+    // enters the body of the group at idx, which goes on from ret once the group is closed.
+    // returns the step to undo it and the state the body begins with, None if the group cannot be called
+    fn call(&self, ctx: &mut Context, group: usize, ret: State, idx: usize) -> Option<(Step, State)> {
+        let &init = self.groups.get(&group)?;
+        if ctx.calls.len() >= self.recursion_limit {
+            return None;
+        }
+        ctx.calls.push(Frame { group, ret, slots: ctx.slots.clone() });
+        let start = ctx.start_slot(group);
+        let old = ctx.slots[start].replace(idx);
+        Some((Step::Call { start, old }, init))
+    }
+
+    // closes the innermost call, what the group captured inside is dropped
+    fn ret(&self, ctx: &mut Context) -> Option<(Step, State)> {
+        let frame = ctx.calls.pop()?;
+        let inner = std::mem::replace(&mut ctx.slots, frame.slots.clone());
+        let ret = frame.ret;
+        Some((Step::Ret { inner, frame }, ret))
     }
 
-    fn is_accepted(&self, state: State) -> bool {
-        state == self.nfa.get_accepted_state()
+    // the end of the first match of the sub-automaton from idx, the other ways it could match are dropped
+    fn atomic(&self, ctx: &mut Context, init: State, accepted: State, idx: usize) -> Option<usize> {
+        let goal = std::mem::replace(&mut ctx.goal, accepted);
+        let end = ctx.end.take();
+        let ret = self.match_dfs(ctx, init, idx);
+        ctx.goal = goal;
+        ctx.end = end;
+        ret
+    }
+
+    // transitions from cur which can be taken at the position idx of input
+    fn get_next_states(&self, ctx: &Context, cur: State, idx: usize) -> Vec<(bool, Node, Trigger)> {
+        let Some(transitions) = self.transitions.get(&cur) else {
+            return Vec::new();
+        };
+        let input = ctx.input;
+        let trigger = ctx.next_char(idx);
+        transitions.iter()
+        .filter_map(|(t, nodes)| {
+            let is_consumed = match t {
+                Trigger::Epsilon | Trigger::Save(_) => false,
+                Trigger::Assert(assertion) if self.holds(*assertion, input, idx) => false,
+                Trigger::Assert(_) => return None,
+                Trigger::Branch(group, matched) if ctx.has_matched(*group) == *matched => false,
+                Trigger::Branch(_, _) => return None,
+                // evaluated by match_dfs, which can run the sub-automaton or compare the captured text
+                Trigger::Look(_) | Trigger::Backref(_, _) | Trigger::Atomic(_, _) | Trigger::Call(_) => false,
+                Trigger::Callout(_) => false,
+                Trigger::Char(c) if Some(*c) == trigger => true,
+                Trigger::Class(set) if trigger.is_some_and(|c| set.contains(c)) => true,
+                Trigger::Char(_) | Trigger::Class(_) => return None,
+            };
+            Some(nodes.iter().map(move |v| (is_consumed, v, t.clone())))
+        })
+        .flatten()
+        .collect()
     }
 
-    fn get_next_states(&self, cur: State, trigger: Option<char>) -> Vec<(bool, Node)> {
-        let (is_consumed, trigger) = match trigger {
-            Some(v) => (true, v),
-            None => (false, 'ε')
+    fn holds(&self, assertion: Assertion, input: &str, idx: usize) -> bool {
+        let next = input[idx..].chars().next();
+        // the character just before idx, None at the start of input
+        let prev = input[..idx].chars().next_back();
+        let is_boundary = |is_word: fn(char) -> bool| {
+            prev.is_some_and(is_word) != next.is_some_and(is_word)
         };
-        match self.nfa.get_transition_table().get(&(cur, trigger)) {
-            Some(v) => {
-                v.iter()
-                .map(|v| (is_consumed, v))
-                .collect()
+        match assertion {
+            Assertion::StartText => idx == 0,
+            Assertion::EndText => next.is_none(),
+            Assertion::StartLine => prev.is_none_or(|c| c == '\n'),
+            Assertion::EndLine => next.is_none_or(|c| c == '\n'),
+            Assertion::CrlfStartLine => match prev {
+                None | Some('\n') => true,
+                Some('\r') => next != Some('\n'),
+                _ => false,
             },
-            None => Vec::new()
+            Assertion::CrlfEndLine => match next {
+                None | Some('\r') => true,
+                Some('\n') => prev != Some('\r'),
+                _ => false,
+            },
+            Assertion::WordBoundary => is_boundary(is_word_char),
+            Assertion::NotWordBoundary => !is_boundary(is_word_char),
+            Assertion::UnicodeWordBoundary => is_boundary(is_unicode_word_char),
+            Assertion::NotUnicodeWordBoundary => !is_boundary(is_unicode_word_char),
         }
     }
 }
 
+fn is_word_char(c: char) -> bool {
+    c == '_' || c.is_ascii_alphanumeric()
+}
+
+fn is_unicode_word_char(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
 impl Matcher for BackTracer {
-    fn exec(&self, input: &str) -> Vec<String> {
-        let mut begin = 0usize;
-        let mut ret = vec![];
-        while begin <= input.len() {
-            let end = self.match_dfs(input, self.nfa.get_init_state(), begin);
-            match end {
-                Some(end) => {
-                    ret.push(input[begin..end].to_string());
-                    begin += if begin < end {
-                        end - begin
-                    } else {
-                        1
-                    };
-                },
-                None => begin += 1,
-            };
+    fn slots_at(&self, haystack: &str, start: usize, end: Option<usize>) -> Option<Vec<Option<usize>>> {
+        // a match can neither begin in the middle of a character nor beyond the end
+        if !haystack.is_char_boundary(start) {
+            return None;
         }
-        ret
+        let mut ctx = Context::new(haystack, self.nfa.get_accepted_state(), end, self.captures.len());
+        self.match_dfs(&mut ctx, self.nfa.get_init_state(), start)?;
+        Some(ctx.spans().to_vec())
+    }
+
+    fn capture_names(&self) -> Arc<[Option<String>]> {
+        self.captures.clone()
     }
 }
 
 #[cfg(test)]
-mod test;
\ No newline at end of file
+mod test;
@@ -7,6 +7,6 @@ fn create_nfa(
     parser: &mut impl Parser, 
     pattern: &str)
     -> NFA {
-    let ast = parser.parse(&get_tokens(pattern)).unwrap();
-    builder.exec(ast)
+    let ast = parser.parse(&get_tokens(pattern).unwrap()).unwrap();
+    builder.exec(ast).unwrap()
 }
\ No newline at end of file
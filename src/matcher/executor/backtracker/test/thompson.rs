@@ -498,4 +498,381 @@ fn complex_test() {
     let actual = matcher.exec(raw);
     assert_eq!(expected, actual);
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn escape_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"\(a\*\|b\)\\\.");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = r"x(a*|b)\.y";
+    let expected = vec![r"(a*|b)\."];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let raw = r"(aa|b)\.";
+    let expected: Vec<String> = vec![];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"a\.b");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "axb a.b";
+    let expected = vec!["a.b"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"\x41\t\u{7e}");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "A\t~";
+    let expected = vec!["A\t~"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn class_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "[a-z0-9_]*");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "foo_1 Bar";
+    let expected = vec!["foo_1", "", "", "ar", ""];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r#""[^"]*""#);
+    let matcher = BackTracer::new(nfa);
+
+    let raw = r#"say "hi" and "bye""#;
+    let expected = vec![r#""hi""#, r#""bye""#];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"[]a-][\]\-]");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "]] a- -] b]";
+    let expected = vec!["]]", "a-", "-]"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "[^a-z]");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aZb~9";
+    let expected = vec!["Z", "~", "9"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn plus_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "ab+");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "a ab abbb";
+    let expected = vec!["ab", "abbb"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(ab)+");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aababab";
+    let expected = vec!["ababab"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn question_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "colou?r");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "color colour colouur";
+    let expected = vec!["color", "colour"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a?");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "baa";
+    let expected = vec!["", "a", "a", ""];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn lazy_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "<.*?>");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "<h1>aaa</h1>";
+    let expected = vec!["<h1>", "</h1>"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a+?");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aaa";
+    let expected = vec!["a", "a", "a"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "ab??");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "ab";
+    let expected = vec!["a"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a*?b");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aab";
+    let expected = vec!["aab"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn empty_loop_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(a*)*b");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aab b";
+    let expected = vec!["aab", "b"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(a?)+?c");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "xaac";
+    let expected = vec!["aac"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+
+#[test]
+fn repetition_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a{3}");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aa aaaaaaa";
+    let expected = vec!["aaa", "aaa"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a{2,4}");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "a aa aaaaa";
+    let expected = vec!["aa", "aaaa"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(ab){2,}");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "ab abab abababab";
+    let expected = vec!["abab", "abababab"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a{2,3}?");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aaaaa";
+    let expected = vec!["aa", "aa"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "ba{0}c");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "bac bc";
+    let expected = vec!["bc"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn multibyte_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "東京(都|駅)");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "東京駅から東京都へ";
+    let expected = vec!["東京駅", "東京都"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "[^a-z]+");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aあい😀b🍣";
+    let expected = vec!["あい😀", "🍣"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "x*");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "😀xé";
+    let expected = vec!["", "x", "", ""];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn backref_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"(\w+) \1");
+    assert!(nfa.has_backrefs());
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "the the cat sat sat";
+    let expected = vec!["the the", "sat sat"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"(\w+) \w+");
+    assert!(!nfa.has_backrefs());
+}
+
+#[test]
+fn atomic_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(?>ab|a)c");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "abc ac";
+    let expected = vec!["abc", "ac"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    // "a" is never tried once "ab" has matched
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(?>a|ab)c");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "abc ac";
+    let expected = vec!["ac"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a++b|a*+a");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aab aaa";
+    let expected = vec!["aab"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "x?+x|a{1,2}+a");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "x xx aa aaa";
+    let expected = vec!["xx", "aaa"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn recursion_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"\((?:[^()]|(?R))*\)");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "(a(b)c) ((()) (x";
+    let expected = vec!["(a(b)c)", "(())"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    // the group returns to the call and goes on from there
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(a(?1)?b)c");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "aabbc abc aabc";
+    let expected = vec!["aabbc", "abc", "abc"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    // calls nested deeper than the limit fail
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(a(?1)?b)");
+    let matcher = BackTracer::new(nfa).recursion_limit(1);
+
+    let raw = "aaabbb";
+    let expected = vec!["aabb"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn conditional_test() {
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"(\()?\d+(?(1)\))");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "(12) 34 (56";
+    let expected = vec!["(12)", "34", "56"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "(a)?(?(1)b|c)");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "ab c ac";
+    let expected = vec!["ab", "c", "c"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn reentered_backref_test() {
+    // the group is entered again before it is closed, while "\1" still refers to the former span
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), r"(?:(a\1?)b)+");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "ababx abaab";
+    let expected = vec!["abab", "abaab"];
+    let actual = matcher.exec(raw);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn long_input_test() {
+    // the search keeps its path on a stack of its own, which grows with input instead of the call stack
+    let builder = ThompsonWayBuilder::new();
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), "a*");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "a".repeat(10_000);
+    let actual = matcher.exec(&raw);
+    assert_eq!(vec![raw.clone(), "".to_string()], actual);
+
+    let nfa = create_nfa(&builder, &mut LL0Parser::new(), ".*b");
+    let matcher = BackTracer::new(nfa);
+
+    let raw = "a".repeat(20_000) + "b";
+    let actual = matcher.exec(&raw);
+    assert_eq!(vec![raw.clone()], actual);
+
+    // and unwinds as far when the match fails at last
+    let raw = "a".repeat(20_000);
+    assert_eq!(None, matcher.slots_at(&raw, 0, None));
+}
@@ -0,0 +1,94 @@
+use std::ops::Range;
+
+use super::Matcher;
+
+// a matched span which borrows from the haystack
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    pub fn new(haystack: &'h str, start: usize, end: usize) -> Match<'h> {
+        Match { haystack, start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.range()]
+    }
+}
+
+// lazily yields successive non-overlapping matches
+pub struct Matches<'m, 'h, M: Matcher + ?Sized> {
+    matcher: &'m M,
+    haystack: &'h str,
+    next_start: usize,
+}
+
+impl<'m, 'h, M: Matcher + ?Sized> Matches<'m, 'h, M> {
+    pub fn new(matcher: &'m M, haystack: &'h str) -> Matches<'m, 'h, M> {
+        Matches { matcher, haystack, next_start: 0 }
+    }
+}
+
+impl<'h, M: Matcher + ?Sized> Iterator for Matches<'_, 'h, M> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_start > self.haystack.len() {
+            return None;
+        }
+        let m = self.matcher.find_at(self.haystack, self.next_start)?;
+        // an empty match steps over the whole character following it
+        self.next_start = if m.is_empty() {
+            m.end() + self.haystack[m.end()..].chars().next().map_or(1, char::len_utf8)
+        } else {
+            m.end()
+        };
+        Some(m)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::matcher::Match;
+
+    #[test]
+    fn span() {
+        let haystack = "hello world";
+        let m = Match::new(haystack, 6, 11);
+        assert_eq!(m.start(), 6);
+        assert_eq!(m.end(), 11);
+        assert_eq!(m.range(), 6..11);
+        assert_eq!(m.len(), 5);
+        assert!(!m.is_empty());
+        assert_eq!(m.as_str(), "world");
+        assert_eq!(&haystack[m.range()], "world");
+
+        let m = Match::new(haystack, 5, 5);
+        assert!(m.is_empty());
+        assert_eq!(m.as_str(), "");
+    }
+}
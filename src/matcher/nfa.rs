@@ -1,21 +1,31 @@
 pub mod builder;
 
-use std::{collections::{HashMap, HashSet}, fmt::Debug};
+use std::{collections::{HashMap, HashSet}, fmt::Debug, sync::atomic::{AtomicU64, Ordering}};
 
-use crate::utils::list::List;
+use crate::{parser::{class::CharSet, Assertion}, utils::list::List};
 
-static mut STATE_ID: u32 = 0;
+// shared by every automaton of the process, so that states of separate builds never collide.
+// 64 bits are never used up in practice, and a wrap would merge unrelated states, so it panics instead
+pub static STATE_ID: AtomicU64 = AtomicU64::new(0);
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub struct State{
-    id: u32
+    id: u64
 }
 
 
 impl State {
-    pub fn new(id: u32) -> State {
+    pub fn new(id: u64) -> State {
         State{id}
     }
+
+    // a state which no other automaton uses
+    pub fn fresh() -> State {
+        let id = STATE_ID
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| v.checked_add(1))
+            .expect("state ids are exhausted");
+        State{id: id + 1}
+    }
 }
 
 #[macro_export]
@@ -24,10 +34,7 @@ macro_rules! state {
         $crate::matcher::nfa::State::new($id)
     };
     () => {
-        {
-            unsafe { $crate::matcher::nfa::STATE_ID += 1 };
-            $crate::matcher::nfa::State::new(unsafe {$crate::matcher::nfa::STATE_ID})
-        }
+        $crate::matcher::nfa::State::fresh()
     };
 }
 
@@ -36,7 +43,7 @@ pub struct Node(State, u8);
 
 impl PartialOrd for Node {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(&other))
+        Some(self.cmp(other))
     }
 }
 
@@ -56,8 +63,42 @@ impl Node {
     }
 }
 
-pub type Trigger = char;
-pub type Key = (State, char);
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum Trigger {
+    Epsilon,
+    Char(char),
+    // consumes any character in the set
+    Class(CharSet),
+    // records the current position into the capture slot without consuming input
+    Save(usize),
+    // succeeds without consuming input only where the assertion holds
+    Assert(Assertion),
+    // succeeds without consuming input only where the sub-automaton matches (or not)
+    Look(Lookaround),
+    // consumes the text the group of the index captured, ignoring its case if the flag is set
+    Backref(usize, bool),
+    // consumes what the sub-automaton between the two states matches first, without backtracking into it
+    Atomic(State, State),
+    // matches the group of the index from here, then goes on from the target once the group is closed
+    Call(usize),
+    // succeeds without consuming input only if whether the group of the index has matched equals the flag
+    Branch(usize, bool),
+    // succeeds without consuming input if the callback of the search lets the match go on
+    Callout(usize),
+}
+
+// a sub-automaton kept in the same transition table, which is never entered by an ordinary transition
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct Lookaround {
+    pub init_state: State,
+    pub accepted_state: State,
+    // succeeds where the sub-automaton does not match
+    pub negated: bool,
+    // the sub-automaton is reversed and reads the input leftward from the position
+    pub behind: bool,
+}
+
+pub type Key = (State, Trigger);
 
 #[derive(PartialEq, Eq)]
 pub struct NFA {
@@ -65,6 +106,10 @@ pub struct NFA {
     transition_table: HashMap<Key, List<Node>>,
     init_state: State,
     accepted_state: State,
+    // names of the capture groups, indexed by group number
+    captures: Vec<Option<String>>,
+    // whether any transition is a backreference, which only a backtracking executor can run
+    backrefs: bool,
 }
 
 impl NFA {
@@ -73,7 +118,7 @@ impl NFA {
     transition_table: HashMap<Key, List<Node>>,
     init_state: State,
     accepted_state: State,) -> NFA {
-        NFA { states, transition_table, init_state, accepted_state }
+        NFA { states, transition_table, init_state, accepted_state, captures: vec![], backrefs: false }
     }
 
     pub fn transit(&self, q: State, trigger: Trigger) -> Result<List<Node>, &'static str> {
@@ -96,6 +141,14 @@ impl NFA {
     pub fn get_transition_table(&self) -> &HashMap<Key, List<Node>> {
         &self.transition_table
     }
+
+    pub fn get_captures(&self) -> &[Option<String>] {
+        &self.captures
+    }
+
+    pub fn has_backrefs(&self) -> bool {
+        self.backrefs
+    }
 }
 
 impl Debug for NFA {
@@ -105,6 +158,8 @@ impl Debug for NFA {
         .field("transition_table", &self.transition_table)
         .field("init_state", &self.init_state)
         .field("accepted_state", &self.accepted_state)
+        .field("captures", &self.captures)
+        .field("backrefs", &self.backrefs)
         .finish()
     }
 }
\ No newline at end of file
@@ -3,7 +3,12 @@ use crate::parser::Regexp;
 use super::NFA;
 
 pub mod thompson;
+mod error;
+
+pub use error::BuildNFAError;
+
+pub type BuilderResult<T> = Result<T, BuildNFAError>;
 
 pub trait NFABuilder {
-    fn exec(&self, node: Regexp) -> NFA;
-}
\ No newline at end of file
+    fn exec(&self, node: Regexp) -> BuilderResult<NFA>;
+}
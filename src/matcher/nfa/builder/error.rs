@@ -0,0 +1,19 @@
+use std::{error::Error, fmt::Display};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildNFAError {
+    // the automaton would need more states than the limit
+    TooManyStates(usize),
+}
+
+impl Display for BuildNFAError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildNFAError::TooManyStates(limit) => {
+                write!(f, "compiled automaton exceeds the size limit of {} states", limit)
+            },
+        }
+    }
+}
+
+impl Error for BuildNFAError {}
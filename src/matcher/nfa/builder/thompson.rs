@@ -1,169 +1,505 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::{list, matcher::nfa::{Key, List, Node, State, Trigger}, parser::{self, Regexp}, state};
+use crate::{lexer::Token, list, matcher::nfa::{Key, List, Lookaround, Node, State, Trigger}, parser::{self, class::{CharSet, Class}, Assertion, Flags, Greediness, Look, Regexp}, state};
 
-use super::{NFABuilder, NFA};
+use super::{BuildNFAError, BuilderResult, NFABuilder, NFA};
 
-pub struct ThompsonWayBuilder {}
+pub const DEFAULT_SIZE_LIMIT: usize = 100_000;
+
+#[derive(Clone, Copy)]
+pub struct ThompsonWayBuilder {
+    // upper bound of the number of states in the automaton
+    size_limit: usize,
+    // whether "\b" and "\B" treat non-ASCII alphanumerics as word characters
+    unicode_word: bool,
+    // whether "." also matches "\n"
+    dot_all: bool,
+    // whether characters and classes also match the other cases of their characters
+    case_insensitive: bool,
+    // whether "^" and "$" also match at the start and end of lines
+    multi_line: bool,
+    // whether "\r\n" ends a line as well as "\n" in multi-line mode
+    crlf: bool,
+    // builds the automaton of the reversed pattern, which a lookbehind runs leftward
+    reverse: bool,
+}
 
 impl NFABuilder for ThompsonWayBuilder {
-    fn exec(&self, root: Regexp) -> NFA {
-        self.alt(root.val)
+    fn exec(&self, root: Regexp) -> BuilderResult<NFA> {
+        let mut nfa = self.group(0, self.alt(root.val)?);
+        nfa.captures = root.captures;
+        nfa.backrefs = nfa.transition_table
+            .keys()
+            .any(|(_, trigger)| matches!(trigger, Trigger::Backref(_, _)));
+        self.check_size(nfa)
+    }
+}
+
+impl Default for ThompsonWayBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl ThompsonWayBuilder {
     pub fn new() -> ThompsonWayBuilder {
-        ThompsonWayBuilder{}
+        ThompsonWayBuilder{
+            size_limit: DEFAULT_SIZE_LIMIT,
+            unicode_word: false,
+            dot_all: false,
+            case_insensitive: false,
+            multi_line: false,
+            crlf: false,
+            reverse: false,
+        }
     }
 
-    fn alt(&self, ast: parser::Alt) -> NFA {
-        let nfa = if ast.val.is_none() {
-            self.symbol('ε')
-        } else {
-            self.concat(ast.val.unwrap())
+    pub fn size_limit(mut self, limit: usize) -> ThompsonWayBuilder {
+        self.size_limit = limit;
+        self
+    }
+
+    pub fn unicode_word(mut self, yes: bool) -> ThompsonWayBuilder {
+        self.unicode_word = yes;
+        self
+    }
+
+    pub fn dot_all(mut self, yes: bool) -> ThompsonWayBuilder {
+        self.dot_all = yes;
+        self
+    }
+
+    pub fn case_insensitive(mut self, yes: bool) -> ThompsonWayBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    pub fn multi_line(mut self, yes: bool) -> ThompsonWayBuilder {
+        self.multi_line = yes;
+        self
+    }
+
+    pub fn crlf(mut self, yes: bool) -> ThompsonWayBuilder {
+        self.crlf = yes;
+        self
+    }
+
+    // the builder for a part of the pattern with inline flags
+    fn scoped(&self, flags: Flags) -> ThompsonWayBuilder {
+        ThompsonWayBuilder {
+            case_insensitive: flags.case_insensitive.unwrap_or(self.case_insensitive),
+            multi_line: flags.multi_line.unwrap_or(self.multi_line),
+            dot_all: flags.dot_all.unwrap_or(self.dot_all),
+            ..*self
+        }
+    }
+
+    fn alt(&self, ast: parser::Alt) -> BuilderResult<NFA> {
+        let nfa = match ast.val {
+            None => self.epsilon(),
+            Some(concat) => self.concat(concat)?,
         };
         if ast.tail.is_none() {
-            return nfa;
+            return Ok(nfa);
         }
         let tail = *ast.tail.unwrap();
         let init_state = state!();
         let accepted_state = state!();
-        let other = self.alt(tail);
-        NFA::new(
-            HashSet::from([
-                init_state,
-                accepted_state,
-            ]).into_iter()
-            .chain(nfa.states)
-            .chain(other.states)
-            .collect(), 
-            HashMap::from([
-                ((init_state, 'ε'), List::from([
-                    Node(nfa.init_state, 1), 
-                    Node(other.init_state, 1)
-                ].into_iter())),
-                ((nfa.accepted_state, 'ε'), list!(
-                    Node(accepted_state, 1)
-                )),
-                ((other.accepted_state, 'ε'), list!(
-                    Node(accepted_state, 1)
-                )),
-            ]).into_iter()
-            .chain(nfa.transition_table)
-            .chain(other.transition_table)
-            .collect(), 
-            init_state, 
-            accepted_state
-        )
+        let other = self.alt(tail)?;
+        let transitions = [
+            ((init_state, Trigger::Epsilon), List::from([
+                Node(nfa.init_state, 1), 
+                Node(other.init_state, 1)
+            ].into_iter())),
+            ((nfa.accepted_state, Trigger::Epsilon), list!(
+                Node(accepted_state, 1)
+            )),
+            ((other.accepted_state, Trigger::Epsilon), list!(
+                Node(accepted_state, 1)
+            )),
+        ];
+        let nfa = self.union(nfa, other);
+        self.check_size(self.wrap(nfa, transitions, init_state, accepted_state))
     }
 
-    fn concat(&self, ast: parser::Concat) -> NFA {
-        if ast.tail.is_none() {
-            return self.factor(ast.val);
+    // the factors are joined one by one onto the automaton so far, the other way round when reversed
+    fn concat(&self, ast: parser::Concat) -> BuilderResult<NFA> {
+        let mut nfa = self.factor(ast.val)?;
+        let mut tail = ast.tail;
+        while let Some(concat) = tail {
+            let right = self.factor(concat.val)?;
+            nfa = if self.reverse {
+                self.check_size(self.join(right, nfa))?
+            } else {
+                self.check_size(self.join(nfa, right))?
+            };
+            tail = concat.tail;
         }
-        let tail = *ast.tail.unwrap();
-        let left = self.factor(ast.val);
-        let right = self.concat(tail);
-        let init_state = left.init_state;
-        let accepted_state = right.accepted_state;
+        Ok(nfa)
+    }
+
+    // merges the initial state of right into the accepted state of left.
+    // the smaller automaton is moved into the larger one, so that joining copies one by one takes linear time
+    fn join(&self, mut left: NFA, mut right: NFA) -> NFA {
+        if left.transition_table.len() >= right.transition_table.len() {
+            let (from, to) = (right.init_state, left.accepted_state);
+            left.states.extend(right.states.into_iter().filter(|&v| v != from));
+            left.transition_table.extend(right.transition_table.into_iter().map(|((q, trigger), nodes)| {
+                ((if q == from { to } else { q }, trigger), nodes)
+            }));
+            left.accepted_state = right.accepted_state;
+            left
+        } else {
+            // the transitions of right win over those of left, as they would be added last
+            let (from, to) = (left.accepted_state, right.init_state);
+            right.states.extend(left.states.iter().copied().filter(|&v| v != from));
+            for (key, nodes) in self.map_states(&left, |v| if v == from { to } else { v }) {
+                right.transition_table.entry(key).or_insert(nodes);
+            }
+            right.init_state = left.init_state;
+            right
+        }
+    }
+
+    fn factor(&self, ast: parser::Factor) -> BuilderResult<NFA> {
+        let nfa = match ast.q {
+            Some(Token::Repetition(min, max)) => {
+                let child = self.base(ast.val)?;
+                self.repeat(child, min, max, ast.greediness)?
+            },
+            Some(q) => {
+                let child = self.base(ast.val)?;
+                self.reps(child, q.to_char(), ast.greediness)
+            },
+            None => return self.base(ast.val),
+        };
+        // "a*+" is "(?>a*)"
+        if ast.greediness == Greediness::Possessive {
+            Ok(self.atomic(nfa))
+        } else {
+            Ok(nfa)
+        }
+    }
 
-        let concated_transiions = right
-            .transition_table
+    // "*": init -> (child -> child.accepted)* -> accepted
+    // "+": init -> child -> (child.accepted -> child)* -> accepted
+    // "?": init -> child? -> accepted
+    // the lower priority is tried first, so a lazy quantifier prefers leaving over repeating
+    fn reps(&self, child: NFA, q: char, greediness: Greediness) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        let (repeat, leave) = match greediness {
+            Greediness::Greedy | Greediness::Possessive => (1, 2),
+            Greediness::Lazy => (2, 1),
+        };
+        let init_transitions = match q {
+            '+' => list!(Node(child.init_state, repeat)),
+            _ => List::from([
+                Node(accepted_state, leave), 
+                Node(child.init_state, repeat)
+            ].into_iter()),
+        };
+        let child_transitions = match q {
+            '?' => list!(Node(accepted_state, leave)),
+            _ => List::from([
+                Node(accepted_state, leave), 
+                Node(child.init_state, repeat)
+            ].into_iter()),
+        };
+        let transitions = [
+            ((init_state, Trigger::Epsilon), init_transitions),
+            ((child.accepted_state, Trigger::Epsilon), child_transitions),
+        ];
+        self.wrap(child, transitions, init_state, accepted_state)
+    }
+
+    // "{n,m}" is expanded into n copies of child followed by nested optional copies,
+    // i.e. a{2,4} is aa(a(a)?)? and a{2,} is aaa*
+    fn repeat(&self, child: NFA, min: usize, max: Option<usize>, greediness: Greediness) -> BuilderResult<NFA> {
+        let copies = max.unwrap_or(min.saturating_add(1));
+        if child.states.len().saturating_mul(copies) > self.size_limit {
+            return Err(BuildNFAError::TooManyStates(self.size_limit));
+        }
+        // every copy is joined onto the automaton so far, so the whole expansion takes linear time
+        let optional = match max {
+            None => Some(self.reps(self.copy(&child), '*', greediness)),
+            Some(max) => (min..max).fold(None, |tail, _| {
+                let body = match tail {
+                    Some(tail) => self.join(self.copy(&child), tail),
+                    None => self.copy(&child),
+                };
+                Some(self.reps(body, '?', greediness))
+            }),
+        };
+        let nfa = (0..min)
+            .map(|_| self.copy(&child))
+            .chain(optional)
+            .reduce(|left, right| self.join(left, right))
+            .unwrap_or_else(|| self.epsilon());
+        self.check_size(nfa)
+    }
+
+    // the same automaton with fresh states
+    fn copy(&self, nfa: &NFA) -> NFA {
+        let states = nfa.states
             .iter()
-            .map(|v| {
-                if v.0.0 == right.init_state {
-                    ((left.accepted_state, v.0.1), v.1.clone())
-                } else {
-                    (*(v.0), v.1.clone())
-                }
-            })
-            .collect::<HashMap<(State, char), List<Node>>>();
-        let transition_table = left
-            .transition_table
-            .into_iter()
-            .chain(concated_transiions.into_iter())
-            .collect();
+            .map(|&v| (v, state!()))
+            .collect::<HashMap<State, State>>();
+        let transition_table = self.map_states(nfa, |v| states[&v]);
         NFA::new(
-            left
-            .states
-            .into_iter()
-            .chain(
-                right.states
-                .into_iter()
-                .filter(|&v| v != right.init_state)
-                .collect::<HashSet<State>>()
-            )
-            .collect(),
+            states.values().copied().collect(),
             transition_table, 
-            init_state, 
-            accepted_state
+            states[&nfa.init_state],
+            states[&nfa.accepted_state]
         )
     }
 
-    fn factor(&self, ast: parser::Factor) -> NFA {
-        if ast.q.is_some() {
-            self.reps(ast)
+    // the transitions of nfa with every state replaced by f
+    fn map_states(&self, nfa: &NFA, f: impl Fn(State) -> State) -> HashMap<Key, List<Node>> {
+        nfa.transition_table
+            .iter()
+            .map(|((q, trigger), nodes)| {
+                let nodes = nodes.iter()
+                    .map(|v| Node(f(v.state()), v.priority()))
+                    .collect::<Vec<Node>>();
+                // a lookaround refers to the states of its sub-automaton as well
+                let trigger = match trigger {
+                    Trigger::Look(look) => Trigger::Look(Lookaround {
+                        init_state: f(look.init_state),
+                        accepted_state: f(look.accepted_state),
+                        ..*look
+                    }),
+                    Trigger::Atomic(init, accepted) => Trigger::Atomic(f(*init), f(*accepted)),
+                    trigger => trigger.clone(),
+                };
+                ((f(*q), trigger), List::from(nodes.into_iter()))
+            })
+            .collect()
+    }
+
+    // child with the new states and transitions around it, which are added in place instead of copying child
+    fn wrap<const N: usize>(&self, mut child: NFA, transitions: [(Key, List<Node>); N], init_state: State, accepted_state: State) -> NFA {
+        child.states.extend([init_state, accepted_state]);
+        child.transition_table.extend(transitions);
+        NFA::new(child.states, child.transition_table, init_state, accepted_state)
+    }
+
+    // the states and transitions of both automata, the smaller moved into the larger
+    fn union(&self, a: NFA, b: NFA) -> NFA {
+        let (mut large, small) = if a.transition_table.len() >= b.transition_table.len() { (a, b) } else { (b, a) };
+        large.states.extend(small.states);
+        large.transition_table.extend(small.transition_table);
+        large
+    }
+
+    fn check_size(&self, nfa: NFA) -> BuilderResult<NFA> {
+        if nfa.states.len() > self.size_limit {
+            Err(BuildNFAError::TooManyStates(self.size_limit))
         } else {
-            self.base(ast.val)
+            Ok(nfa)
         }
     }
 
-    fn reps(&self, ast: parser::Factor) -> NFA {
+    fn base(&self, ast: parser::Base) -> BuilderResult<NFA> {
+        Ok(match ast {
+            parser::Base::Alt(v) => self.alt(*v)?,
+            parser::Base::Capture(index, _, v) => self.group(index, self.alt(*v)?),
+            parser::Base::Scoped(flags, v) => self.scoped(flags).alt(*v)?,
+            parser::Base::Atomic(v) => self.atomic(self.alt(*v)?),
+            parser::Base::Look(look, v) => {
+                let behind = matches!(look, Look::Behind | Look::NegativeBehind);
+                let child = ThompsonWayBuilder { reverse: behind, ..*self }.alt(*v)?;
+                self.look(look, child)
+            },
+            parser::Base::Char(Token::Dot) => self.dot(),
+            parser::Base::Class(class) => self.class(class),
+            parser::Base::Assert(assertion) => self.assert(assertion),
+            parser::Base::Backref(index) => self.backref(index),
+            parser::Base::Call(index) => self.call(index),
+            parser::Base::Callout(number) => self.callout(number),
+            parser::Base::Conditional(index, yes, no) => self.conditional(index, self.alt(*yes)?, self.alt(*no)?),
+            parser::Base::Char(c) if self.case_insensitive => {
+                self.class(Class { set: CharSet::from_char(c.to_char()), negated: false })
+            },
+            parser::Base::Char(c) => self.symbol(c.to_char()),
+        })
+    }
+
+    // surrounds child with the transitions which save the start and end of the group,
+    // a reversed automaton meets the end first
+    fn group(&self, index: usize, child: NFA) -> NFA {
         let init_state = state!();
         let accepted_state = state!();
-        let child = self.base(ast.val);
-        let transition_table = HashMap::from([
-            ((init_state, 'ε'), List::from([
-                Node(accepted_state, 2), 
-                Node(child.init_state, 1)
-            ].into_iter())),
-            ((child.accepted_state, 'ε'), List::from([
-                Node(accepted_state, 2), 
+        let (first, last) = if self.reverse {
+            (index * 2 + 1, index * 2)
+        } else {
+            (index * 2, index * 2 + 1)
+        };
+        let transitions = [
+            ((init_state, Trigger::Save(first)), list!(
                 Node(child.init_state, 1)
-            ].into_iter())),
-        ]).into_iter()
-        .chain(child.transition_table.into_iter())
-        .collect::<HashMap<(State, Trigger), List<Node>>>();
+            )),
+            ((child.accepted_state, Trigger::Save(last)), list!(
+                Node(accepted_state, 1)
+            )),
+        ];
+        self.wrap(child, transitions, init_state, accepted_state)
+    }
+
+    // child becomes a sub-automaton which the lookaround transition runs on its own
+    fn look(&self, look: Look, child: NFA) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        let lookaround = Lookaround {
+            init_state: child.init_state,
+            accepted_state: child.accepted_state,
+            negated: matches!(look, Look::NegativeAhead | Look::NegativeBehind),
+            behind: matches!(look, Look::Behind | Look::NegativeBehind),
+        };
+        let transitions = [
+            ((init_state, Trigger::Look(lookaround)), list!(
+                Node(accepted_state, 1)
+            )),
+        ];
+        self.wrap(child, transitions, init_state, accepted_state)
+    }
 
+    // child becomes a sub-automaton which the atomic transition runs on its own
+    fn atomic(&self, child: NFA) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        let transitions = [
+            ((init_state, Trigger::Atomic(child.init_state, child.accepted_state)), list!(
+                Node(accepted_state, 1)
+            )),
+        ];
+        self.wrap(child, transitions, init_state, accepted_state)
+    }
+
+    // goes into yes if the group has matched, otherwise into no
+    fn conditional(&self, index: usize, yes: NFA, no: NFA) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        let transitions = [
+            ((init_state, Trigger::Branch(index, true)), list!(
+                Node(yes.init_state, 1)
+            )),
+            ((init_state, Trigger::Branch(index, false)), list!(
+                Node(no.init_state, 1)
+            )),
+            ((yes.accepted_state, Trigger::Epsilon), list!(
+                Node(accepted_state, 1)
+            )),
+            ((no.accepted_state, Trigger::Epsilon), list!(
+                Node(accepted_state, 1)
+            )),
+        ];
+        let nfa = self.union(yes, no);
+        self.wrap(nfa, transitions, init_state, accepted_state)
+    }
+
+    fn epsilon(&self) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
         NFA::new(
-            HashSet::from([init_state, accepted_state])
-                .into_iter()
-                .chain(child.states)
-                .collect(),
-            transition_table,
-            init_state,
+            HashSet::from([init_state, accepted_state]),
+            HashMap::from([((init_state, Trigger::Epsilon), list!(Node(accepted_state, 1)))]),
+            init_state, 
             accepted_state
         )
     }
 
-    fn base(&self, ast: parser::Base) -> NFA {
-        match ast {
-            parser::Base::Alt(v) => self.alt(*v),
-            parser::Base::Char(c) => self.symbol(c.to_char()),
-        }
+    fn symbol(&self, c: char) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        NFA::new(
+            HashSet::from([init_state, accepted_state]),
+            HashMap::from([((init_state, Trigger::Char(c)), list!(Node(accepted_state, 1)))]),
+            init_state, 
+            accepted_state
+        )
     }
 
-    fn symbol(&self, c: char) -> NFA {
+    // a whole class is a single transition instead of one per character.
+    // the case is folded before the negation, so [^a] matches neither "a" nor "A"
+    fn class(&self, class: Class) -> NFA {
+        let set = if self.case_insensitive {
+            Class { set: class.set.case_fold(), negated: class.negated }.to_set()
+        } else {
+            class.to_set()
+        };
         let init_state = state!();
         let accepted_state = state!();
-        let table = match c {
-            '.' => {
-                (0x20u32..0x7eu32)
-                .filter_map(char::from_u32)
-                .map(|v| {
-                    ((init_state, v), list!(Node(accepted_state, 1)))
-                })
-                .collect::<HashMap<Key, List<Node>>>()
-            },
-            _ => HashMap::from([((init_state, c), list!(Node(accepted_state, 1)))])
+        NFA::new(
+            HashSet::from([init_state, accepted_state]),
+            HashMap::from([((init_state, Trigger::Class(set)), list!(Node(accepted_state, 1)))]),
+            init_state, 
+            accepted_state
+        )
+    }
+
+    fn backref(&self, index: usize) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        NFA::new(
+            HashSet::from([init_state, accepted_state]),
+            HashMap::from([((init_state, Trigger::Backref(index, self.case_insensitive)), list!(Node(accepted_state, 1)))]),
+            init_state, 
+            accepted_state
+        )
+    }
+
+    // enters the automaton of the group, whose closing save returns to the accepted state
+    fn call(&self, index: usize) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        NFA::new(
+            HashSet::from([init_state, accepted_state]),
+            HashMap::from([((init_state, Trigger::Call(index)), list!(Node(accepted_state, 1)))]),
+            init_state, 
+            accepted_state
+        )
+    }
+
+    fn callout(&self, number: usize) -> NFA {
+        let init_state = state!();
+        let accepted_state = state!();
+        NFA::new(
+            HashSet::from([init_state, accepted_state]),
+            HashMap::from([((init_state, Trigger::Callout(number)), list!(Node(accepted_state, 1)))]),
+            init_state, 
+            accepted_state
+        )
+    }
+
+    fn assert(&self, assertion: Assertion) -> NFA {
+        let assertion = match assertion {
+            Assertion::WordBoundary if self.unicode_word => Assertion::UnicodeWordBoundary,
+            Assertion::NotWordBoundary if self.unicode_word => Assertion::NotUnicodeWordBoundary,
+            Assertion::StartLine if !self.multi_line => Assertion::StartText,
+            Assertion::EndLine if !self.multi_line => Assertion::EndText,
+            Assertion::StartLine if self.crlf => Assertion::CrlfStartLine,
+            Assertion::EndLine if self.crlf => Assertion::CrlfEndLine,
+            v => v,
         };
+        let init_state = state!();
+        let accepted_state = state!();
         NFA::new(
             HashSet::from([init_state, accepted_state]),
-            table,
+            HashMap::from([((init_state, Trigger::Assert(assertion)), list!(Node(accepted_state, 1)))]),
             init_state, 
             accepted_state
         )
     }
 
+    // any scalar value, except "\n" unless dot_all is set
+    fn dot(&self) -> NFA {
+        let newline = if self.dot_all {
+            CharSet::default()
+        } else {
+            CharSet::from_char('\n')
+        };
+        self.class(Class { set: newline, negated: true })
+    }
+
 }
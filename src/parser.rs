@@ -1,14 +1,21 @@
 mod error;
 pub mod ll0_parser;
+pub mod class;
+
+pub use error::{ParseErrorKind, ParseRegexpError};
 
 use crate::lexer::Token;
 
+use self::class::Class;
+
 pub type ParserResult<T> = Result<T, error::ParseRegexpError>;
 
 // <regex> ::= <alt>
+// captures[i] is the name of the i-th capture group, captures[0] being the whole match
 #[derive(Debug, PartialEq, Eq)]
 pub struct Regexp {
-    pub val: Alt
+    pub val: Alt,
+    pub captures: Vec<Option<String>>,
 }
 
 // <alt> ::= ε | <concat> | <concat> "|" <alt>
@@ -19,27 +26,124 @@ pub struct Alt {
     pub tail: Option<Box<Alt>>,
 }
 
-// <concat> ::= <factor> <concat> | <factor>
+// <concat> ::= <factor> <concat> | <factor> | <flags> <concat>?
 #[derive(Debug, PartialEq, Eq)]
 pub struct Concat {
     pub val: Factor,
     pub tail: Option<Box<Concat>>
 }
 
-// <factor> ::= <base> <quantifier> | <base>
+// <factor> ::= <base> <quantifier> | <base> <quantifier> "?" | <base> <quantifier> "+" | <base>
+// <quantifier> ::= "*" | "+" | "?" | "{" <n> "}" | "{" <n> ",}" | "{" <n> "," <m> "}"
 #[derive(Debug, PartialEq, Eq)]
 pub struct Factor {
     pub val: Base,
-    pub q: Option<Token>
+    pub q: Option<Token>,
+    pub greediness: Greediness,
+}
+
+// which way a quantifier is tried first when backtracking
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Greediness {
+    // as many repetitions as possible
+    #[default]
+    Greedy,
+    // as few repetitions as possible, written with a trailing "?"
+    Lazy,
+    // as many repetitions as possible and never gives any back, written with a trailing "+"
+    Possessive,
+}
+
+// inline options which change how the rest of a group is compiled,
+// None leaves the option as it is outside
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Flags {
+    // "i"
+    pub case_insensitive: Option<bool>,
+    // "m", "^" and "$" also match at the start and end of lines
+    pub multi_line: Option<bool>,
+    // "s", "." also matches "\n"
+    pub dot_all: Option<bool>,
+    // "x", whitespace in the pattern is ignored, handled by the lexer alone
+    pub verbose: Option<bool>,
+}
+
+impl Flags {
+    // the flags in effect when `other` is set after self
+    pub fn then(self, other: Flags) -> Flags {
+        Flags {
+            case_insensitive: other.case_insensitive.or(self.case_insensitive),
+            multi_line: other.multi_line.or(self.multi_line),
+            dot_all: other.dot_all.or(self.dot_all),
+            verbose: other.verbose.or(self.verbose),
+        }
+    }
+}
+
+// which side of the position a lookaround group looks at, and whether it must not match there
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Look {
+    // "(?="
+    Ahead,
+    // "(?!"
+    NegativeAhead,
+    // "(?<="
+    Behind,
+    // "(?<!"
+    NegativeBehind,
+}
+
+// zero-width conditions on the position in the input
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Assertion {
+    // "\A", or "^" out of multi-line mode
+    StartText,
+    // "\z", or "$" out of multi-line mode
+    EndText,
+    // "^", at the start of input or right after "\n"
+    StartLine,
+    // "$", at the end of input or right before "\n"
+    EndLine,
+    // the same as above, but "\r\n" is also a line terminator and never split
+    CrlfStartLine,
+    CrlfEndLine,
+    // "\b", between a word character and a non-word character
+    WordBoundary,
+    // "\B", between two word characters or two non-word characters
+    NotWordBoundary,
+    // the same as above, but any alphanumeric character is a word character, not only ASCII
+    UnicodeWordBoundary,
+    NotUnicodeWordBoundary,
 }
 
-// <base> ::= <character> | "(" <regex> ")"
+// <base> ::= <character> | "." | <class> | <assertion> | <backreference> | "(" <regex> ")" | "(?:" <regex> ")" | "(?<" <name> ">" <regex> ")"
+//          | "(?" <flags> ":" <regex> ")" | "(?>" <regex> ")" | "(?=" <regex> ")" | "(?!" <regex> ")"
+//          | "(?<=" <regex> ")" | "(?<!" <regex> ")"
 #[derive(Debug, PartialEq, Eq)]
 pub enum Base {
     Char(Token),
-    Alt(Box<Alt>)
+    Class(Class),
+    Assert(Assertion),
+    // non-capturing group
+    Alt(Box<Alt>),
+    // capturing group with its index and optional name
+    Capture(usize, Option<String>, Box<Alt>),
+    // "(?flags:" <regex> ")", or the part of a group following "(?flags)"
+    Scoped(Flags, Box<Alt>),
+    // zero-width group which matches <regex> without consuming input
+    Look(Look, Box<Alt>),
+    // "(?>" <regex> ")", the first way <regex> matches is never backtracked into
+    Atomic(Box<Alt>),
+    // "\1" or "\k<name>", the text last captured by the group of the index
+    Backref(usize),
+    // "(?R)", "(?1)" or "(?&name)", matches the group of the index again at this point
+    Call(usize),
+    // "(?(1)" <yes> "|" <no> ")", matches <yes> if the group of the index has matched and <no> otherwise
+    Conditional(usize, Box<Alt>, Box<Alt>),
+    // "(?C1)", hands control to the user's callback when the match reaches it
+    Callout(usize),
 }
 
 pub trait Parser {
-    fn parse(&mut self, tokens: &Vec<Token>) -> ParserResult<Regexp>;
+    fn parse(&mut self, tokens: &[Token]) -> ParserResult<Regexp>;
 }
@@ -0,0 +1,311 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+// a set of characters kept as sorted, non-overlapping and non-adjacent inclusive ranges
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct CharSet {
+    ranges: Vec<(char, char)>,
+}
+
+impl CharSet {
+    pub fn new<I: IntoIterator<Item = (char, char)>>(ranges: I) -> CharSet {
+        let mut ranges = ranges
+            .into_iter()
+            .filter(|(lo, hi)| lo <= hi)
+            .collect::<Vec<(char, char)>>();
+        ranges.sort();
+        let mut normalized: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+        for (lo, hi) in ranges {
+            match normalized.last_mut() {
+                Some(last) if next_char(last.1).is_none_or(|v| v >= lo) => {
+                    last.1 = last.1.max(hi);
+                },
+                _ => normalized.push((lo, hi)),
+            }
+        }
+        CharSet { ranges: normalized }
+    }
+
+    pub fn from_char(c: char) -> CharSet {
+        CharSet { ranges: vec![(c, c)] }
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(lo, hi)| {
+                if hi < c {
+                    std::cmp::Ordering::Less
+                } else if lo > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn union(&self, other: &CharSet) -> CharSet {
+        CharSet::new(self.ranges.iter().chain(other.ranges.iter()).copied())
+    }
+
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        self.complement().union(&other.complement()).complement()
+    }
+
+    pub fn difference(&self, other: &CharSet) -> CharSet {
+        self.intersection(&other.complement())
+    }
+
+    // the characters in exactly one of the two sets
+    pub fn symmetric_difference(&self, other: &CharSet) -> CharSet {
+        self.difference(other).union(&other.difference(self))
+    }
+
+    // every scalar value which is not in the set
+    pub fn complement(&self) -> CharSet {
+        let mut ranges = vec![];
+        let mut lo = Some('\0');
+        for &(start, end) in self.ranges.iter() {
+            if let (Some(v), Some(prev)) = (lo, prev_char(start)) {
+                if v < start {
+                    ranges.push((v, prev));
+                }
+            }
+            lo = next_char(end);
+        }
+        if let Some(v) = lo {
+            ranges.push((v, char::MAX));
+        }
+        CharSet { ranges }
+    }
+}
+
+// no character after the Adlam block has another case
+const LAST_CASED: char = '\u{1E943}';
+
+impl CharSet {
+    // adds every character which equals one in the set regardless of case
+    pub fn case_fold(&self) -> CharSet {
+        let orbits = orbits();
+        let len = self.ranges.iter().map(|&(lo, hi)| hi as usize - lo as usize + 1).sum::<usize>();
+        let folded: Vec<char> = if len <= orbits.len() {
+            self.ranges.iter()
+                .flat_map(|&(lo, hi)| lo..=hi)
+                .filter_map(|c| orbits.get(&c))
+                .flatten()
+                .copied()
+                .collect()
+        } else {
+            orbits.iter()
+                .filter(|(&c, _)| self.contains(c))
+                .flat_map(|(_, orbit)| orbit.iter().copied())
+                .collect()
+        };
+        CharSet::new(self.ranges.iter().copied().chain(folded.into_iter().map(|v| (v, v))))
+    }
+}
+
+// the smallest character which equals c regardless of case
+pub fn simple_fold(c: char) -> char {
+    orbits().get(&c).map_or(c, |orbit| orbit[0])
+}
+
+// the characters which equal each other regardless of case, sorted, for each cased character.
+// the simple mappings of std are closed over, so that "k" equals the Kelvin sign "\u{212A}" which maps to it,
+// and "ς" equals "σ" as both map to "Σ". a character which maps to several characters (e.g. "ß" to "SS")
+// gets nothing from it, nor does "ı", which folds into "I" only in Turkic languages
+fn orbits() -> &'static HashMap<char, Vec<char>> {
+    static ORBITS: OnceLock<HashMap<char, Vec<char>>> = OnceLock::new();
+    ORBITS.get_or_init(|| {
+        // each character points to a smaller one of its orbit, until the smallest
+        let mut parent = HashMap::<char, char>::new();
+        let root = |parent: &HashMap<char, char>, mut c: char| {
+            while let Some(&v) = parent.get(&c) {
+                c = v;
+            }
+            c
+        };
+        for c in ('\0'..=LAST_CASED).filter(|&v| v != '\u{131}') {
+            for other in [single(c.to_lowercase()), single(c.to_uppercase())].into_iter().flatten() {
+                let (a, b) = (root(&parent, c), root(&parent, other));
+                if a != b {
+                    parent.insert(a.max(b), a.min(b));
+                }
+            }
+        }
+        let mut members = HashMap::<char, Vec<char>>::new();
+        for &c in parent.keys() {
+            members.entry(root(&parent, c)).or_insert_with_key(|&v| vec![v]).push(c);
+        }
+        members.into_values()
+            .flat_map(|mut orbit| {
+                orbit.sort();
+                orbit.clone().into_iter().map(move |v| (v, orbit.clone()))
+            })
+            .collect()
+    })
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
+// the next scalar value, skipping the surrogate block
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        '\0' => None,
+        c => char::from_u32(c as u32 - 1),
+    }
+}
+
+// <class> ::= "[" "^"? <item>+ (<set op> <item>+)* "]", where an item can be a nested class
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Class {
+    pub set: CharSet,
+    pub negated: bool,
+}
+
+impl Class {
+    // "\d", "\w", "\s" and their negations "\D", "\W", "\S", all ASCII only
+    pub fn perl(c: char) -> Class {
+        let set = match c.to_ascii_lowercase() {
+            'd' => CharSet::new([('0', '9')]),
+            'w' => CharSet::new([('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+            _ => CharSet::new([('\t', '\r'), (' ', ' ')]),
+        };
+        Class { set, negated: c.is_ascii_uppercase() }
+    }
+
+    // the characters the class matches
+    pub fn to_set(&self) -> CharSet {
+        if self.negated {
+            self.set.complement()
+        } else {
+            self.set.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::class::{simple_fold, CharSet, Class};
+
+    #[test]
+    fn normalize() {
+        let set = CharSet::new([('x', 'z'), ('a', 'c'), ('b', 'e'), ('f', 'f'), ('q', 'p')]);
+        assert_eq!(set.ranges(), &[('a', 'f'), ('x', 'z')]);
+
+        let set = CharSet::new([('\u{E000}', '\u{E001}'), ('a', '\u{D7FF}')]);
+        assert_eq!(set.ranges(), &[('a', '\u{E001}')]);
+
+        assert!(CharSet::new([]).is_empty());
+    }
+
+    #[test]
+    fn contains() {
+        let set = CharSet::new([('a', 'c'), ('x', 'z'), ('0', '0')]);
+        for c in "abcxyz0".chars() {
+            assert!(set.contains(c));
+        }
+        for c in "dw19A".chars() {
+            assert!(!set.contains(c));
+        }
+        assert!(!CharSet::new([]).contains('a'));
+    }
+
+    #[test]
+    fn union_and_complement() {
+        let set = CharSet::from_char('a').union(&CharSet::new([('b', 'd'), ('0', '9')]));
+        assert_eq!(set.ranges(), &[('0', '9'), ('a', 'd')]);
+
+        let complement = CharSet::new([('\0', 'a'), ('c', 'c')]).complement();
+        assert_eq!(complement.ranges(), &[('b', 'b'), ('d', char::MAX)]);
+
+        let complement = CharSet::new([('b', 'y'), ('\u{E000}', char::MAX)]).complement();
+        assert_eq!(complement.ranges(), &[('\0', 'a'), ('z', '\u{D7FF}')]);
+
+        assert_eq!(CharSet::new([]).complement().ranges(), &[('\0', char::MAX)]);
+        assert!(CharSet::new([('\0', char::MAX)]).complement().is_empty());
+        assert_eq!(set.complement().complement(), set);
+    }
+
+    #[test]
+    fn set_operations() {
+        let letters = CharSet::new([('a', 'z')]);
+        let vowels = CharSet::new([('a', 'a'), ('e', 'e'), ('i', 'i'), ('o', 'o'), ('u', 'u')]);
+        let consonants = letters.difference(&vowels);
+        assert_eq!(consonants.ranges(), &[('b', 'd'), ('f', 'h'), ('j', 'n'), ('p', 't'), ('v', 'z')]);
+        assert_eq!(letters.intersection(&vowels), vowels);
+        assert_eq!(vowels.symmetric_difference(&letters), consonants);
+
+        let set = CharSet::new([('0', '9'), ('a', 'f')]).symmetric_difference(&CharSet::new([('5', 'c')]));
+        assert_eq!(set.ranges(), &[('0', '4'), (':', '`'), ('d', 'f')]);
+
+        assert!(vowels.intersection(&CharSet::new([('0', '9')])).is_empty());
+        assert!(vowels.difference(&letters).is_empty());
+    }
+
+    #[test]
+    fn case_fold() {
+        let set = CharSet::new([('a', 'c'), ('X', 'X'), ('0', '9')]).case_fold();
+        assert_eq!(set.ranges(), &[('0', '9'), ('A', 'C'), ('X', 'X'), ('a', 'c'), ('x', 'x')]);
+
+        let set = CharSet::new([('é', 'é'), ('Σ', 'Σ'), ('ß', 'ß')]).case_fold();
+        for c in "éÉσΣß".chars() {
+            assert!(set.contains(c));
+        }
+        assert!(!set.contains('S'));
+        // "ς" maps to "Σ", so it equals every other sigma although nothing maps back to it
+        assert!(set.contains('ς'));
+        assert!(CharSet::from_char('ς').case_fold().contains('σ'));
+
+        // the Kelvin sign maps to "k", and so equals "K" as well
+        for c in ['k', 'K', '\u{212A}'] {
+            let set = CharSet::from_char(c).case_fold();
+            assert_eq!(set.ranges(), &[('K', 'K'), ('k', 'k'), ('\u{212A}', '\u{212A}')]);
+        }
+        assert!(!CharSet::from_char('i').case_fold().contains('\u{131}'));
+        assert_eq!(simple_fold('\u{212A}'), simple_fold('k'));
+        assert_eq!(simple_fold('ς'), simple_fold('Σ'));
+        assert_eq!(simple_fold('1'), '1');
+
+        // the whole range is left as it is
+        let set = CharSet::new([('\0', char::MAX)]);
+        assert_eq!(set.case_fold(), set);
+    }
+
+    #[test]
+    fn perl() {
+        let digit = Class::perl('d');
+        assert_eq!(digit.to_set().ranges(), &[('0', '9')]);
+        assert!(!digit.negated);
+
+        let space = Class::perl('s').to_set();
+        for c in " \t\n\x0B\x0C\r".chars() {
+            assert!(space.contains(c));
+        }
+        assert!(!space.contains('\u{A0}'));
+
+        let non_word = Class::perl('W');
+        assert!(non_word.negated);
+        assert!(non_word.to_set().contains('-'));
+        assert!(!non_word.to_set().contains('_'));
+        assert!(non_word.to_set().contains('é'));
+    }
+}
@@ -4,21 +4,54 @@ use crate::parser::Token;
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParseRegexpError {
     cause: Option<Token>,
-    idx: usize
+    idx: usize,
+    kind: ParseErrorKind,
+}
+
+// what is wrong with the cause
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParseErrorKind {
+    // the token cannot come there
+    Unexpected,
+    // the token is valid, but not inside a lookbehind, which is matched reversed
+    UnsupportedInLookbehind,
 }
 
 impl ParseRegexpError {
     pub fn new(cause: Option<Token>, idx: usize) -> ParseRegexpError {
-        ParseRegexpError {cause, idx}
+        ParseRegexpError {cause, idx, kind: ParseErrorKind::Unexpected}
+    }
+
+    pub fn unsupported_in_lookbehind(cause: Token, idx: usize) -> ParseRegexpError {
+        ParseRegexpError {cause: Some(cause), idx, kind: ParseErrorKind::UnsupportedInLookbehind}
+    }
+
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+}
+
+// the name of the construct the token begins
+fn construct(token: &Token) -> &'static str {
+    match token {
+        Token::Backref(_) | Token::NamedBackref(_) => "backreference",
+        Token::Recurse(_) | Token::NamedRecurse(_) => "subroutine call",
+        Token::Conditional(_) | Token::NamedConditional(_) => "conditional group",
+        Token::Atomic => "atomic group",
+        Token::Quantifier(_) => "possessive quantifier",
+        _ => "construct",
     }
 }
 
 impl Display for ParseRegexpError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.cause == None {
-            return write!(f, "変なところで終わってんで");
+        match (&self.cause, self.kind) {
+            (None, _) => write!(f, "unexpected end of pattern at {}", self.idx),
+            (Some(cause), ParseErrorKind::UnsupportedInLookbehind) => {
+                write!(f, "{} at {} is unsupported in lookbehind", construct(cause), self.idx)
+            },
+            (Some(cause), ParseErrorKind::Unexpected) => write!(f, "cannot parse {:?} at {}", cause, self.idx),
         }
-        write!(f, "cannot parse {:?} at {:?}", self.cause, self.idx)
     }
 }
 
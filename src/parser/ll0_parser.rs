@@ -1,25 +1,63 @@
 use crate::lexer::Token;
 
-use super::{error::ParseRegexpError, Alt, Base, Concat, Factor, Parser, ParserResult, Regexp};
+use super::{class::{CharSet, Class}, error::ParseRegexpError, Alt, Assertion, Base, Concat, Factor, Flags, Greediness, Look, Parser, ParserResult, Regexp};
 
 pub struct LL0Parser {
     next_idx: usize,
+    captures: Vec<Option<String>>,
+    // flags set so far in the current group
+    flags: Flags,
+    // the outermost lookbehind being parsed, numbered in the order they are opened.
+    // a lookbehind is compiled reversed, so what refers to a group in it is met before the group
+    behind: Option<usize>,
+    // the number of lookbehinds opened so far
+    lookbehinds: usize,
+    // the outermost lookbehind each capture group is in
+    reversed: Vec<Option<usize>>,
+    // names of every capture group of the pattern, known before parsing so that a call can refer to a later group
+    groups: Vec<Option<String>>,
+    // the group and the position of each call, checked once the whole pattern is parsed
+    calls: Vec<(usize, usize)>,
+}
+
+impl Default for LL0Parser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LL0Parser {
     pub fn new() -> LL0Parser {
-        LL0Parser { next_idx: 0 }
+        LL0Parser {
+            next_idx: 0,
+            captures: vec![None],
+            flags: Flags::default(),
+            behind: None,
+            lookbehinds: 0,
+            reversed: vec![None],
+            groups: vec![None],
+            calls: vec![],
+        }
     }
 
     // <alt> ::= ε | <concat> | <alt> "|" <alt>
 
     // <alt> ::= ε | <concat><alt'>
     // <alt'> ::= ε | "|" <alt><alt'>
-    fn parse_alt(&mut self, tokens: &Vec<Token>) -> ParserResult<Alt> {
+    fn parse_alt(&mut self, tokens: &[Token]) -> ParserResult<Alt> {
+        let flags = self.flags;
         let concat = match self.get_next_token(tokens) {
             None | Some(Token::Selector) | Some(Token::Rparen) => None,
             Some(_c) =>  Some(self.parse_concat(tokens)?)
         };
+        // flags set in a former alternative also apply to this one
+        let concat = match concat {
+            Some(concat) if flags != Flags::default() => Some(Concat {
+                val: scoped(flags, Some(concat)),
+                tail: None,
+            }),
+            concat => concat,
+        };
 
         match self.get_next_token(tokens) {
             Some(Token::Selector) => {
@@ -31,67 +69,338 @@ impl LL0Parser {
         }
     }
 
-    // <concat> ::= <factor> <concat> | <factor> [$, "|"]
-    fn parse_concat(&mut self, tokens: &Vec<Token>) -> ParserResult<Concat> {
-        let factor = self.parse_factor(tokens)?;
+    // <concat> ::= <factor> <concat> | <factor> [$, "|", ")"] | <flags> <concat>?
+    // the factors are collected before they are linked, so that a long literal does not recurse once per character
+    fn parse_concat(&mut self, tokens: &[Token]) -> ParserResult<Concat> {
+        let mut factors = vec![];
+        let last = loop {
+            if let Some(Token::SetFlags(flags)) = self.get_next_token(tokens) {
+                self.next_idx += 1;
+                self.flags = self.flags.then(flags);
+                let rest = match self.get_next_token(tokens) {
+                    None | Some(Token::Selector) | Some(Token::Rparen) => None,
+                    Some(_c) => Some(self.parse_concat(tokens)?),
+                };
+                break scoped(flags, rest);
+            }
+            let factor = self.parse_factor(tokens)?;
+            match self.get_next_token(tokens) {
+                None | Some(Token::Selector) | Some(Token::Rparen) => break factor,
+                Some(_c) => factors.push(factor),
+            }
+        };
+        let concat = Concat { val: last, tail: None };
+        Ok(factors.into_iter().rfold(concat, |tail, val| Concat { val, tail: Some(Box::new(tail)) }))
+    }
+
+    // <factor> ::= <base> <quantifier> ("?" | "+")? | <base> [$, "|", Char, "("]
+    fn parse_factor(&mut self, tokens: &[Token]) -> ParserResult<Factor> {
+        let base = self.parse_base(tokens)?;
         match self.get_next_token(tokens) {
-            Some(Token::Char(_)) | Some(Token::Lparen) => {
-                let tail = self.parse_concat(tokens)?;
-                Ok(Concat{val: factor, tail: Some(Box::new(tail)) })
+            Some(q @ Token::Quantifier(_)) | Some(q @ Token::Repetition(_, _)) => {
+                self.next_idx += 1;
+                let greediness = self.parse_greediness(tokens)?;
+                Ok(Factor{val: base, q: Some(q), greediness})
             },
-            _ => Ok(Concat{val: factor, tail: None})
+            _ => Ok(Factor{val: base, q: None, greediness: Greediness::Greedy})
         }
     }
 
-    // <factor> ::= <base> <quantifier> | <base> [$, "|", Char, "("]
-    fn parse_factor(&mut self, tokens: &Vec<Token>) -> ParserResult<Factor> {
-        let base = self.parse_base(tokens)?;
+    // a reversed lookbehind would commit to other ways than the pattern read forward, so nothing is possessive in it
+    fn parse_greediness(&mut self, tokens: &[Token]) -> ParserResult<Greediness> {
         match self.get_next_token(tokens) {
-            Some(Token::Quantifier(c)) => {
+            Some(Token::Quantifier('?')) => {
                 self.next_idx += 1;
-                Ok(Factor{val: base, q: Some(Token::Quantifier(c))})
+                Ok(Greediness::Lazy)
             },
-            _ => Ok(Factor{val: base, q: None})
+            Some(Token::Quantifier('+')) if self.behind.is_none() => {
+                self.next_idx += 1;
+                Ok(Greediness::Possessive)
+            },
+            Some(q @ Token::Quantifier('+')) => Err(ParseRegexpError::unsupported_in_lookbehind(q, self.next_idx)),
+            _ => Ok(Greediness::Greedy),
         }
     }
 
-    // <base> ::= <character> | "(" <alt> ")" [$, "|", Char, "(", Quantifier]
-    fn parse_base(&mut self, tokens: &Vec<Token>) -> ParserResult<Base> {
+    // <base> ::= <character> | <group> [$, "|", Char, "(", Quantifier]
+    fn parse_base(&mut self, tokens: &[Token]) -> ParserResult<Base> {
         match self.get_next_token(tokens) {
             Some(Token::Char(c)) => {
                 self.next_idx += 1;
                 Ok(Base::Char(Token::Char(c)))
             },
+            Some(Token::Dot) => {
+                self.next_idx += 1;
+                Ok(Base::Char(Token::Dot))
+            },
+            Some(Token::LBracket) => {
+                self.next_idx += 1;
+                Ok(Base::Class(self.parse_class(tokens)?))
+            },
+            Some(Token::PerlClass(c)) => {
+                self.next_idx += 1;
+                Ok(Base::Class(Class::perl(c)))
+            },
+            // only a group opened before can be referred to, and not from the lookbehind it is in
+            Some(Token::Backref(index)) if index < self.captures.len() => {
+                if self.in_this_lookbehind(index) {
+                    return Err(ParseRegexpError::unsupported_in_lookbehind(Token::Backref(index), self.next_idx));
+                }
+                self.next_idx += 1;
+                Ok(Base::Backref(index))
+            },
+            Some(Token::NamedBackref(ref name)) if self.captures.contains(&Some(name.clone())) => {
+                let index = self.captures.iter().position(|v| v.as_ref() == Some(name)).unwrap();
+                if self.in_this_lookbehind(index) {
+                    return Err(ParseRegexpError::unsupported_in_lookbehind(Token::NamedBackref(name.clone()), self.next_idx));
+                }
+                self.next_idx += 1;
+                Ok(Base::Backref(index))
+            },
+            // a call can refer to any group, but neither from nor into a lookbehind
+            Some(Token::Recurse(index)) if self.behind.is_none() => {
+                self.calls.push((index, self.next_idx));
+                self.next_idx += 1;
+                Ok(Base::Call(index))
+            },
+            Some(Token::NamedRecurse(ref name)) if self.behind.is_none() && self.groups.contains(&Some(name.clone())) => {
+                let index = self.groups.iter().position(|v| v.as_ref() == Some(name)).unwrap();
+                self.calls.push((index, self.next_idx));
+                self.next_idx += 1;
+                Ok(Base::Call(index))
+            },
+            Some(call @ (Token::Recurse(_) | Token::NamedRecurse(_))) if self.behind.is_some() => {
+                Err(ParseRegexpError::unsupported_in_lookbehind(call, self.next_idx))
+            },
+            Some(Token::Callout(number)) => {
+                self.next_idx += 1;
+                Ok(Base::Callout(number))
+            },
+            Some(Token::Assert(c)) => {
+                self.next_idx += 1;
+                Ok(Base::Assert(match c {
+                    '^' => Assertion::StartLine,
+                    '$' => Assertion::EndLine,
+                    'A' => Assertion::StartText,
+                    'b' => Assertion::WordBoundary,
+                    'B' => Assertion::NotWordBoundary,
+                    _ => Assertion::EndText,
+                }))
+            },
             Some(Token::Lparen) => {
                 self.next_idx += 1;
-                let regexp = Ok(Base::Alt(Box::new(self.parse_alt(tokens)?)));
-                let next = self.get_next_token(tokens);
-                if next != Some(Token::Rparen) {
-                    return Err(ParseRegexpError::new(next, self.next_idx));
+                let index = self.new_capture(None);
+                Ok(Base::Capture(index, None, Box::new(self.parse_group(tokens)?)))
+            },
+            Some(Token::NamedGroup(name)) => {
+                if self.captures.contains(&Some(name.clone())) {
+                    return Err(ParseRegexpError::new(Some(Token::NamedGroup(name)), self.next_idx));
                 }
                 self.next_idx += 1;
-                regexp
+                let index = self.new_capture(Some(name.clone()));
+                Ok(Base::Capture(index, Some(name), Box::new(self.parse_group(tokens)?)))
+            },
+            Some(Token::NonCapture) => {
+                self.next_idx += 1;
+                Ok(Base::Alt(Box::new(self.parse_group(tokens)?)))
+            },
+            // the same as a possessive quantifier, an atomic group commits to other ways when reversed
+            Some(Token::Atomic) if self.behind.is_none() => {
+                self.next_idx += 1;
+                Ok(Base::Atomic(Box::new(self.parse_group(tokens)?)))
+            },
+            Some(Token::Atomic) => Err(ParseRegexpError::unsupported_in_lookbehind(Token::Atomic, self.next_idx)),
+            Some(Token::LookAhead) => {
+                self.next_idx += 1;
+                Ok(Base::Look(Look::Ahead, Box::new(self.parse_group(tokens)?)))
+            },
+            Some(Token::NegativeLookAhead) => {
+                self.next_idx += 1;
+                Ok(Base::Look(Look::NegativeAhead, Box::new(self.parse_group(tokens)?)))
+            },
+            Some(Token::LookBehind) => {
+                self.next_idx += 1;
+                Ok(Base::Look(Look::Behind, Box::new(self.parse_behind(tokens)?)))
+            },
+            Some(Token::NegativeLookBehind) => {
+                self.next_idx += 1;
+                Ok(Base::Look(Look::NegativeBehind, Box::new(self.parse_behind(tokens)?)))
+            },
+            Some(Token::ScopedFlags(flags)) => {
+                self.next_idx += 1;
+                Ok(Base::Scoped(flags, Box::new(self.parse_group(tokens)?)))
+            },
+            // the condition refers to a group opened before, other than the whole pattern,
+            // and not from the lookbehind the group is in
+            Some(Token::Conditional(index)) if 0 < index && index < self.captures.len() => {
+                if self.in_this_lookbehind(index) {
+                    return Err(ParseRegexpError::unsupported_in_lookbehind(Token::Conditional(index), self.next_idx));
+                }
+                self.parse_conditional(tokens, index)
+            },
+            Some(Token::NamedConditional(ref name)) if self.captures.contains(&Some(name.clone())) => {
+                let index = self.captures.iter().position(|v| v.as_ref() == Some(name)).unwrap();
+                if self.in_this_lookbehind(index) {
+                    return Err(ParseRegexpError::unsupported_in_lookbehind(Token::NamedConditional(name.clone()), self.next_idx));
+                }
+                self.parse_conditional(tokens, index)
             },
             cause => Err(ParseRegexpError::new(cause, self.next_idx)),
         }
     }
 
-    fn get_next_token(&self, tokens: &Vec<Token>) -> Option<Token> {
-        if self.next_idx >= tokens.len() {
-            None
-        } else {
-            Some(tokens[self.next_idx])
+    // <group> ::= <alt> ")", the opening token has already been consumed
+    fn parse_group(&mut self, tokens: &[Token]) -> ParserResult<Alt> {
+        let flags = std::mem::take(&mut self.flags);
+        let alt = self.parse_alt(tokens)?;
+        let next = self.get_next_token(tokens);
+        if next != Some(Token::Rparen) {
+            return Err(ParseRegexpError::new(next, self.next_idx));
+        }
+        self.next_idx += 1;
+        self.flags = flags;
+        Ok(alt)
+    }
+
+    // <conditional> ::= <condition> <alt> ")" with at most two alternatives, the missing one matches empty
+    fn parse_conditional(&mut self, tokens: &[Token], index: usize) -> ParserResult<Base> {
+        let start = self.next_idx;
+        self.next_idx += 1;
+        let Alt { val, tail } = self.parse_group(tokens)?;
+        let no = match tail {
+            None => Alt { val: None, tail: None },
+            Some(no) if no.tail.is_none() => *no,
+            Some(_) => return Err(ParseRegexpError::new(tokens.get(start).cloned(), start)),
+        };
+        Ok(Base::Conditional(index, Box::new(Alt { val, tail: None }), Box::new(no)))
+    }
+
+    // whether the group is in the lookbehind being parsed, where it is captured only after what follows it
+    fn in_this_lookbehind(&self, index: usize) -> bool {
+        self.behind.is_some() && self.reversed[index] == self.behind
+    }
+
+    // a lookbehind nested in another one is reversed along with it
+    fn parse_behind(&mut self, tokens: &[Token]) -> ParserResult<Alt> {
+        let behind = self.behind;
+        if behind.is_none() {
+            self.behind = Some(self.lookbehinds);
+            self.lookbehinds += 1;
+        }
+        let alt = self.parse_group(tokens);
+        self.behind = behind;
+        alt
+    }
+
+    // <class> ::= "[" "^"? <union> (<set op> <union>)* "]", "[" has already been consumed
+    // the set operators are evaluated from left to right, the negation last
+    fn parse_class(&mut self, tokens: &[Token]) -> ParserResult<Class> {
+        let negated = self.get_next_token(tokens) == Some(Token::Negation);
+        if negated {
+            self.next_idx += 1;
+        }
+        let mut set = self.parse_class_union(tokens)?;
+        loop {
+            match self.get_next_token(tokens) {
+                Some(Token::RBracket) => {
+                    self.next_idx += 1;
+                    return Ok(Class { set, negated });
+                },
+                Some(Token::SetOp(op)) => {
+                    self.next_idx += 1;
+                    let other = self.parse_class_union(tokens)?;
+                    set = match op {
+                        '&' => set.intersection(&other),
+                        '-' => set.difference(&other),
+                        _ => set.symmetric_difference(&other),
+                    };
+                },
+                cause => return Err(ParseRegexpError::new(cause, self.next_idx)),
+            }
+        }
+    }
+
+    // <union> ::= <item>+
+    fn parse_class_union(&mut self, tokens: &[Token]) -> ParserResult<CharSet> {
+        let mut set = self.parse_class_item(tokens)?;
+        while !matches!(self.get_next_token(tokens), Some(Token::RBracket) | Some(Token::SetOp(_))) {
+            set = set.union(&self.parse_class_item(tokens)?);
         }
+        Ok(set)
+    }
+
+    // <item> ::= <character> | <character> "-" <character> | <perl class> | <class>
+    // a "-" which does not stand between two characters is literal
+    fn parse_class_item(&mut self, tokens: &[Token]) -> ParserResult<CharSet> {
+        let lo = match self.get_next_token(tokens) {
+            Some(Token::Char(c)) => c,
+            Some(Token::Hyphen) => '-',
+            Some(Token::PerlClass(c)) => {
+                self.next_idx += 1;
+                return Ok(Class::perl(c).to_set());
+            },
+            Some(Token::LBracket) => {
+                self.next_idx += 1;
+                return Ok(self.parse_class(tokens)?.to_set());
+            },
+            cause => return Err(ParseRegexpError::new(cause, self.next_idx)),
+        };
+        self.next_idx += 1;
+        if self.get_next_token(tokens) != Some(Token::Hyphen) {
+            return Ok(CharSet::from_char(lo));
+        }
+        self.next_idx += 1;
+        match self.get_next_token(tokens) {
+            Some(Token::Char(hi)) if lo <= hi => {
+                self.next_idx += 1;
+                Ok(CharSet::new([(lo, hi)]))
+            },
+            Some(Token::RBracket) => Ok(CharSet::new([(lo, lo), ('-', '-')])),
+            cause => Err(ParseRegexpError::new(cause, self.next_idx)),
+        }
+    }
+
+    // capture groups are numbered by their opening parenthesis from left to right
+    fn new_capture(&mut self, name: Option<String>) -> usize {
+        self.captures.push(name);
+        self.reversed.push(self.behind);
+        self.captures.len() - 1
+    }
+
+    fn can_call(&self, index: usize) -> bool {
+        index < self.captures.len() && self.reversed[index].is_none()
+    }
+
+    fn get_next_token(&self, tokens: &[Token]) -> Option<Token> {
+        tokens.get(self.next_idx).cloned()
+    }
+}
+
+// a factor which compiles concat with flags
+fn scoped(flags: Flags, concat: Option<Concat>) -> Factor {
+    Factor {
+        val: Base::Scoped(flags, Box::new(Alt { val: concat, tail: None })),
+        q: None,
+        greediness: Greediness::Greedy,
     }
 }
 
 impl Parser for LL0Parser {
-    fn parse(&mut self, tokens: &Vec<Token>) -> ParserResult<Regexp> {
+    fn parse(&mut self, tokens: &[Token]) -> ParserResult<Regexp> {
+        *self = LL0Parser::new();
+        self.groups.extend(tokens.iter().filter_map(|v| match v {
+            Token::Lparen => Some(None),
+            Token::NamedGroup(name) => Some(Some(name.clone())),
+            _ => None,
+        }));
         let alt = self.parse_alt(tokens)?;
         if let Some(c) = self.get_next_token(tokens) {
             return Err(ParseRegexpError::new(Some(c), self.next_idx));
         }
-        Ok(Regexp { val: alt })
+        if let Some(&(_, idx)) = self.calls.iter().find(|v| !self.can_call(v.0)) {
+            return Err(ParseRegexpError::new(tokens.get(idx).cloned(), idx));
+        }
+        Ok(Regexp { val: alt, captures: std::mem::take(&mut self.captures) })
     }
 }
 
@@ -104,34 +413,53 @@ mod test {
         fn start_with_quantifier() {
             let tokens = vec![Token::Quantifier('*')];
             let actual = LL0Parser::new().parse(&tokens);
-            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0]), 0usize));
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
             
             let tokens = vec![Token::Quantifier('*'), Token::Char('c')];
             let actual = LL0Parser::new().parse(&tokens);
-            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0]), 0usize));
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
 
             let tokens = vec![Token::Lparen, Token::Quantifier('*'), Token::Rparen];
             let actual = LL0Parser::new().parse(&tokens);
-            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[1]), 1usize));
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[1].clone()), 1usize));
         }
 
         #[test]
         fn token_before_quantifier_is_not_char() {
             let tokens = vec![Token::Char('a'), Token::Quantifier('*'), Token::Quantifier('*')];
             let actual = LL0Parser::new().parse(&tokens);
-            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2]), 2usize));
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2].clone()), 2usize));
 
             let tokens = vec![Token::Char('a'), Token::Quantifier('|'), Token::Quantifier('*')];
             let actual = LL0Parser::new().parse(&tokens);
-            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2]), 2usize));
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2].clone()), 2usize));
 
             let tokens = vec![Token::Char('a'), Token::Quantifier('('), Token::Quantifier('*')];
             let actual = LL0Parser::new().parse(&tokens);
-            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2]), 2usize));
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2].clone()), 2usize));
 
             let tokens = vec![Token::Char('a'), Token::Char('a'), Token::Quantifier('*'), Token::Char('a'), Token::Quantifier('*'), Token::Quantifier('*')];
             let actual = LL0Parser::new().parse(&tokens);
-            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[5]), 5usize));
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[5].clone()), 5usize));
+
+            let tokens = vec![Token::Char('a'), Token::Repetition(2, None), Token::Quantifier('*')];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2].clone()), 2usize));
+        }
+
+        #[test]
+        fn continuous_lazy_quantifier() {
+            let tokens = vec![Token::Char('a'), Token::Quantifier('*'), Token::Quantifier('?'), Token::Quantifier('?')];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[3].clone()), 3usize));
+
+            let tokens = vec![Token::Char('a'), Token::Quantifier('?'), Token::Quantifier('+'), Token::Quantifier('+')];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[3].clone()), 3usize));
+
+            let tokens = vec![Token::Char('a'), Token::Quantifier('?'), Token::Quantifier('+'), Token::Quantifier('?')];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[3].clone()), 3usize));
         }
 
         #[test]
@@ -160,15 +488,215 @@ mod test {
             let actual = LL0Parser::new().parse(&tokens);
             assert_eq!(actual.unwrap_err(), ParseRegexpError::new(None, 4usize));
         }
+
+        #[test]
+        fn invalid_class() {
+            // []
+            let tokens = vec![Token::LBracket, Token::RBracket];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(Token::RBracket), 1usize));
+
+            // [a
+            let tokens = vec![Token::LBracket, Token::Char('a')];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(None, 2usize));
+
+            // [z-a]
+            let tokens = vec![Token::LBracket, Token::Char('z'), Token::Hyphen, Token::Char('a'), Token::RBracket];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(Token::Char('a')), 3usize));
+
+            // [a&&]
+            let tokens = vec![Token::LBracket, Token::Char('a'), Token::SetOp('&'), Token::RBracket];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(Token::RBracket), 3usize));
+
+            // [a[b]
+            let tokens = vec![Token::LBracket, Token::Char('a'), Token::LBracket, Token::Char('b'), Token::RBracket];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(None, 5usize));
+        }
+
+        #[test]
+        fn duplicated_name() {
+            let name = Token::NamedGroup("x".to_string());
+            let tokens = vec![name.clone(), Token::Rparen, name.clone(), Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(name), 2usize));
+        }
+
+        #[test]
+        fn unknown_backref() {
+            let tokens = vec![Token::Backref(1), Token::Lparen, Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
+
+            let tokens = vec![Token::Lparen, Token::Rparen, Token::NamedBackref("x".to_string())];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[2].clone()), 2usize));
+        }
+
+        // a reversed lookbehind would meet these before the group they depend on
+        #[test]
+        fn unsupported_in_lookbehind() {
+            // (?<=(a)\1)
+            let tokens = vec![Token::LookBehind, Token::Lparen, Token::Char('a'), Token::Rparen, Token::Backref(1), Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[4].clone(), 4usize));
+
+            // (?<=(?<k>a)(?<!\k<k>))
+            let tokens = vec![
+                Token::LookBehind,
+                Token::NamedGroup("k".to_string()),
+                Token::Char('a'),
+                Token::Rparen,
+                Token::NegativeLookBehind,
+                Token::NamedBackref("k".to_string()),
+                Token::Rparen,
+                Token::Rparen,
+            ];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[5].clone(), 5usize));
+
+            // (?<=(a))(?<=\1) refers to a group of another lookbehind
+            let tokens = vec![
+                Token::LookBehind,
+                Token::Lparen,
+                Token::Char('a'),
+                Token::Rparen,
+                Token::Rparen,
+                Token::LookBehind,
+                Token::Backref(1),
+                Token::Rparen,
+            ];
+            assert!(LL0Parser::new().parse(&tokens).is_ok());
+
+            // (?<=(a)(?(1)b))
+            let tokens = vec![
+                Token::LookBehind,
+                Token::Lparen,
+                Token::Char('a'),
+                Token::Rparen,
+                Token::Conditional(1),
+                Token::Char('b'),
+                Token::Rparen,
+                Token::Rparen,
+            ];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[4].clone(), 4usize));
+
+            // (?<=(?<k>a)(?(<k>)b))
+            let tokens = vec![
+                Token::LookBehind,
+                Token::NamedGroup("k".to_string()),
+                Token::Char('a'),
+                Token::Rparen,
+                Token::NamedConditional("k".to_string()),
+                Token::Char('b'),
+                Token::Rparen,
+                Token::Rparen,
+            ];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[4].clone(), 4usize));
+
+            // (?<=(?>a)), also in a lookahead nested in it
+            let tokens = vec![Token::LookBehind, Token::Atomic, Token::Char('a'), Token::Rparen, Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[1].clone(), 1usize));
+
+            let tokens = vec![Token::LookBehind, Token::LookAhead, Token::Atomic, Token::Char('a'), Token::Rparen, Token::Rparen, Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[2].clone(), 2usize));
+
+            // (?<!a*+)
+            let tokens = vec![
+                Token::NegativeLookBehind,
+                Token::Char('a'),
+                Token::Quantifier('*'),
+                Token::Quantifier('+'),
+                Token::Rparen,
+            ];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[3].clone(), 3usize));
+        }
+
+        #[test]
+        fn invalid_conditional() {
+            let tokens = vec![Token::Conditional(1), Token::Rparen, Token::Lparen, Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
+
+            let tokens = vec![Token::Conditional(0), Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
+
+            // (a)(?(1)b|c|d)
+            let tokens = vec![
+                Token::Lparen,
+                Token::Char('a'),
+                Token::Rparen,
+                Token::Conditional(1),
+                Token::Char('b'),
+                Token::Selector,
+                Token::Char('c'),
+                Token::Selector,
+                Token::Char('d'),
+                Token::Rparen,
+            ];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[3].clone()), 3usize));
+        }
+
+        #[test]
+        fn invalid_call() {
+            let tokens = vec![Token::Recurse(2), Token::Lparen, Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
+
+            // a lookbehind can neither call nor be called
+            let tokens = vec![Token::LookBehind, Token::Recurse(0), Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::unsupported_in_lookbehind(tokens[1].clone(), 1usize));
+
+            let name = Token::NamedGroup("x".to_string());
+            let tokens = vec![Token::LookBehind, name, Token::Rparen, Token::Rparen, Token::NamedRecurse("x".to_string())];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[4].clone()), 4usize));
+
+            // also when the group comes after the call
+            let name = Token::NamedGroup("x".to_string());
+            let tokens = vec![Token::NamedRecurse("x".to_string()), Token::LookBehind, name, Token::Rparen, Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
+
+            let tokens = vec![Token::NamedRecurse("y".to_string()), Token::NamedGroup("x".to_string()), Token::Rparen];
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual.unwrap_err(), ParseRegexpError::new(Some(tokens[0].clone()), 0usize));
+        }
+
+        #[test]
+        fn reused_parser() {
+            // nothing of a former pattern is left over
+            let mut parser = LL0Parser::new();
+            let tokens = vec![Token::LookBehind, Token::Lparen, Token::Char('a'), Token::Rparen, Token::Rparen, Token::Char('b')];
+            assert!(parser.parse(&tokens).is_ok());
+            let tokens = vec![Token::Lparen, Token::Char('a'), Token::Rparen, Token::Recurse(1)];
+            assert!(parser.parse(&tokens).is_ok());
+
+            let tokens = vec![Token::Recurse(1), Token::Rparen];
+            assert!(parser.parse(&tokens).is_err());
+            let tokens = vec![Token::Lparen, Token::Rparen, Token::NamedBackref("x".to_string())];
+            assert!(parser.parse(&tokens).is_err());
+        }
     }
 
     mod valid {
         use std::collections::HashMap;
 
-        use crate::{lexer::Token, parser::{ll0_parser::LL0Parser, Alt, Base, Concat, Factor, Parser, Regexp}};
+        use crate::{lexer::Token, parser::{class::{CharSet, Class}, ll0_parser::LL0Parser, Alt, Assertion, Base, Concat, Factor, Flags, Greediness, Look, Parser, Regexp}};
 
         fn wrap_regexp(val: Alt) -> Regexp {
-            Regexp { val }
+            Regexp { val, captures: vec![None] }
         }
 
         fn wrap_alt(val: Option<Concat>, tail: Option<Alt>) -> Alt {
@@ -186,7 +714,7 @@ mod test {
         }
 
         fn wrap_factor(val: Base, q: Option<Token>) -> Factor {
-            Factor{val, q}
+            Factor{val, q, greediness: Greediness::Greedy}
         }
 
         fn create_concat(mut v: Vec<Factor>) -> Concat {
@@ -221,7 +749,7 @@ mod test {
             let expected = wrap_regexp(
                 wrap_alt(
                     Some(wrap_concat(
-                        wrap_factor(Base::Char(tokens[0]), None), 
+                        wrap_factor(Base::Char(tokens[0].clone()), None), 
                         None)), 
                 None), 
             );
@@ -234,11 +762,12 @@ mod test {
                 Factor{
                     val: Base::Char(v),
                     q: None,
+                    greediness: Greediness::Greedy,
                 }
             })
             .collect();
             let expected_concat = create_concat(expected_factors);
-            let expected = Regexp { val: (Alt { val:Some(expected_concat), tail: None }) };
+            let expected = Regexp { val: (Alt { val:Some(expected_concat), tail: None }), captures: vec![None] };
             let actual = LL0Parser::new().parse(&tokens);
             assert_eq!(actual, Ok(expected));
         }
@@ -274,11 +803,13 @@ mod test {
             assert_eq!(actual, Ok(expected));
 
             let tokens = vec![Token::Lparen, Token::Selector, Token::Rparen];
-            let expected = wrap_regexp(
-                wrap_alt(
+            let expected = Regexp {
+                val: wrap_alt(
                     Some(wrap_concat(
                         wrap_factor(
-                            Base::Alt(
+                            Base::Capture(
+                                1,
+                                None,
                                 Box::new(wrap_alt(
                                     None,
                                     Some(Alt { val: None, tail: None })
@@ -288,8 +819,9 @@ mod test {
                         None
                     )),
                     None
-                )
-            );
+                ),
+                captures: vec![None, None],
+            };
             let actual = LL0Parser::new().parse(&tokens);
             assert_eq!(actual, Ok(expected));
         }
@@ -355,6 +887,7 @@ mod test {
                         val: Factor { 
                             val: Base::Char(Token::Char('a')), 
                             q: Some(Token::Quantifier('*')),
+                            greediness: Greediness::Greedy,
                         },
                         tail: None,
                     }, 
@@ -367,19 +900,472 @@ mod test {
                     val: Some(
                         Concat{
                             val: Factor { 
-                                val: Base::Alt(Box::new(expected_inner_node)), 
+                                val: Base::Capture(1, None, Box::new(expected_inner_node)), 
                                 q: None,
+                                greediness: Greediness::Greedy,
                             },
                             tail: None,
                         }, 
                     ),
                     tail: None
-                }
+                },
+                captures: vec![None, None],
             };
             let actual = LL0Parser::new().parse(&tokens);
             assert_eq!(actual, Ok(expected));
         }
 
+        #[test]
+        fn non_capturing_and_named_group() {
+            // (?:a)(?<x>b)(c)
+            let tokens = vec![
+                Token::NonCapture,
+                Token::Char('a'),
+                Token::Rparen,
+                Token::NamedGroup("x".to_string()),
+                Token::Char('b'),
+                Token::Rparen,
+                Token::Lparen,
+                Token::Char('c'),
+                Token::Rparen,
+            ];
+            let group = |c| Box::new(wrap_alt(
+                Some(wrap_concat(wrap_factor(Base::Char(Token::Char(c)), None), None)),
+                None
+            ));
+            let expected = Regexp {
+                val: wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Alt(group('a')), None),
+                        wrap_factor(Base::Capture(1, Some("x".to_string()), group('b')), None),
+                        wrap_factor(Base::Capture(2, None, group('c')), None),
+                    ])),
+                    None
+                ),
+                captures: vec![None, Some("x".to_string()), None],
+            };
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn possessive_quantifier() {
+            // (?>a)?+
+            let tokens = vec![
+                Token::Atomic,
+                Token::Char('a'),
+                Token::Rparen,
+                Token::Quantifier('?'),
+                Token::Quantifier('+'),
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(wrap_concat(
+                        Factor {
+                            val: Base::Atomic(Box::new(wrap_alt(
+                                Some(wrap_concat(wrap_factor(Base::Char(Token::Char('a')), None), None)),
+                                None
+                            ))),
+                            q: Some(Token::Quantifier('?')),
+                            greediness: Greediness::Possessive,
+                        },
+                        None
+                    )),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn lazy_quantifier() {
+            let tokens = vec![
+                Token::Char('a'),
+                Token::Quantifier('+'),
+                Token::Quantifier('?'),
+                Token::Char('b'),
+                Token::Quantifier('?'),
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(create_concat(vec![
+                        Factor {
+                            val: Base::Char(Token::Char('a')),
+                            q: Some(Token::Quantifier('+')),
+                            greediness: Greediness::Lazy,
+                        },
+                        wrap_factor(Base::Char(Token::Char('b')), Some(Token::Quantifier('?'))),
+                    ])),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn repetition() {
+            let tokens = vec![
+                Token::Char('a'),
+                Token::Repetition(2, Some(3)),
+                Token::Quantifier('?'),
+                Token::Char('b'),
+                Token::Repetition(1, None),
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(create_concat(vec![
+                        Factor {
+                            val: Base::Char(Token::Char('a')),
+                            q: Some(Token::Repetition(2, Some(3))),
+                            greediness: Greediness::Lazy,
+                        },
+                        wrap_factor(Base::Char(Token::Char('b')), Some(Token::Repetition(1, None))),
+                    ])),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn flags() {
+            // a(?i)b|c
+            let i = Flags { case_insensitive: Some(true), ..Flags::default() };
+            let tokens = vec![
+                Token::Char('a'),
+                Token::SetFlags(i),
+                Token::Char('b'),
+                Token::Selector,
+                Token::Char('c'),
+            ];
+            let scoped = |val: Concat| wrap_concat(
+                wrap_factor(Base::Scoped(i, Box::new(wrap_alt(Some(val), None))), None),
+                None
+            );
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(wrap_concat(
+                        wrap_factor(Base::Char(Token::Char('a')), None),
+                        Some(scoped(wrap_concat(wrap_factor(Base::Char(Token::Char('b')), None), None)))
+                    )),
+                    Some(wrap_alt(
+                        Some(scoped(wrap_concat(wrap_factor(Base::Char(Token::Char('c')), None), None))),
+                        None
+                    ))
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+
+            // the flags end with the group
+            let tokens = vec![
+                Token::NonCapture,
+                Token::SetFlags(i),
+                Token::Rparen,
+                Token::Char('a'),
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(wrap_concat(
+                        wrap_factor(Base::Alt(Box::new(wrap_alt(
+                            Some(wrap_concat(
+                                wrap_factor(Base::Scoped(i, Box::new(wrap_alt(None, None))), None),
+                                None
+                            )),
+                            None
+                        ))), None),
+                        Some(wrap_concat(wrap_factor(Base::Char(Token::Char('a')), None), None))
+                    )),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn backref() {
+            // (?<x>a)\1\k<x>
+            let tokens = vec![
+                Token::NamedGroup("x".to_string()),
+                Token::Char('a'),
+                Token::Rparen,
+                Token::Backref(1),
+                Token::NamedBackref("x".to_string()),
+            ];
+            let expected = Regexp {
+                val: wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Capture(1, Some("x".to_string()), Box::new(wrap_alt(
+                            Some(wrap_concat(wrap_factor(Base::Char(Token::Char('a')), None), None)),
+                            None
+                        ))), None),
+                        wrap_factor(Base::Backref(1), None),
+                        wrap_factor(Base::Backref(1), None),
+                    ])),
+                    None
+                ),
+                captures: vec![None, Some("x".to_string())],
+            };
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn call() {
+            // (?<x>a(?&x)?)(?R)
+            let tokens = vec![
+                Token::NamedGroup("x".to_string()),
+                Token::Char('a'),
+                Token::NamedRecurse("x".to_string()),
+                Token::Quantifier('?'),
+                Token::Rparen,
+                Token::Recurse(0),
+            ];
+            let expected = Regexp {
+                val: wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Capture(1, Some("x".to_string()), Box::new(wrap_alt(
+                            Some(create_concat(vec![
+                                wrap_factor(Base::Char(Token::Char('a')), None),
+                                wrap_factor(Base::Call(1), Some(Token::Quantifier('?'))),
+                            ])),
+                            None
+                        ))), None),
+                        wrap_factor(Base::Call(0), None),
+                    ])),
+                    None
+                ),
+                captures: vec![None, Some("x".to_string())],
+            };
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn conditional() {
+            // (?<x>a)?(?(<x>)b)
+            let tokens = vec![
+                Token::NamedGroup("x".to_string()),
+                Token::Char('a'),
+                Token::Rparen,
+                Token::Quantifier('?'),
+                Token::NamedConditional("x".to_string()),
+                Token::Char('b'),
+                Token::Rparen,
+            ];
+            let expected = Regexp {
+                val: wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Capture(1, Some("x".to_string()), Box::new(wrap_alt(
+                            Some(wrap_concat(wrap_factor(Base::Char(Token::Char('a')), None), None)),
+                            None
+                        ))), Some(Token::Quantifier('?'))),
+                        wrap_factor(Base::Conditional(
+                            1,
+                            Box::new(wrap_alt(Some(wrap_concat(wrap_factor(Base::Char(Token::Char('b')), None), None)), None)),
+                            Box::new(wrap_alt(None, None)),
+                        ), None),
+                    ])),
+                    None
+                ),
+                captures: vec![None, Some("x".to_string())],
+            };
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn callout() {
+            // a(?C1)
+            let tokens = vec![Token::Char('a'), Token::Callout(1)];
+            let expected = Regexp {
+                val: wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Char(Token::Char('a')), None),
+                        wrap_factor(Base::Callout(1), None),
+                    ])),
+                    None
+                ),
+                captures: vec![None],
+            };
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn lookaround() {
+            // a(?!b)
+            let tokens = vec![
+                Token::Char('a'),
+                Token::NegativeLookAhead,
+                Token::Char('b'),
+                Token::Rparen,
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Char(Token::Char('a')), None),
+                        wrap_factor(Base::Look(Look::NegativeAhead, Box::new(wrap_alt(
+                            Some(wrap_concat(wrap_factor(Base::Char(Token::Char('b')), None), None)),
+                            None
+                        ))), None),
+                    ])),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn scoped_flags() {
+            // (?s-i:.)*
+            let flags = Flags { case_insensitive: Some(false), dot_all: Some(true), ..Flags::default() };
+            let tokens = vec![
+                Token::ScopedFlags(flags),
+                Token::Dot,
+                Token::Rparen,
+                Token::Quantifier('*'),
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(wrap_concat(
+                        wrap_factor(
+                            Base::Scoped(flags, Box::new(wrap_alt(
+                                Some(wrap_concat(wrap_factor(Base::Char(Token::Dot), None), None)),
+                                None
+                            ))),
+                            Some(Token::Quantifier('*'))
+                        ),
+                        None
+                    )),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn perl_class() {
+            // \D[\w.-]
+            let tokens = vec![
+                Token::PerlClass('D'),
+                Token::LBracket,
+                Token::PerlClass('w'),
+                Token::Char('.'),
+                Token::Char('-'),
+                Token::RBracket,
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Class(Class {
+                            set: CharSet::new([('0', '9')]),
+                            negated: true,
+                        }), None),
+                        wrap_factor(Base::Class(Class {
+                            set: CharSet::new([('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z'), ('.', '.'), ('-', '-')]),
+                            negated: false,
+                        }), None),
+                    ])),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn class_set_operation() {
+            // [^a-f--[b-d]~~\d&&[0-9c]]
+            let tokens = vec![
+                Token::LBracket,
+                Token::Negation,
+                Token::Char('a'),
+                Token::Hyphen,
+                Token::Char('f'),
+                Token::SetOp('-'),
+                Token::LBracket,
+                Token::Char('b'),
+                Token::Hyphen,
+                Token::Char('d'),
+                Token::RBracket,
+                Token::SetOp('~'),
+                Token::PerlClass('d'),
+                Token::SetOp('&'),
+                Token::LBracket,
+                Token::Char('0'),
+                Token::Hyphen,
+                Token::Char('9'),
+                Token::Char('c'),
+                Token::RBracket,
+                Token::RBracket,
+            ];
+            // evaluated from left to right, and reduced to a single set
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(wrap_concat(wrap_factor(Base::Class(Class {
+                        set: CharSet::new([('0', '9')]),
+                        negated: true,
+                    }), None), None)),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn assertion() {
+            let tokens = vec![Token::Assert('^'), Token::Char('a'), Token::Assert('z')];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(create_concat(vec![
+                        wrap_factor(Base::Assert(Assertion::StartLine), None),
+                        wrap_factor(Base::Char(Token::Char('a')), None),
+                        wrap_factor(Base::Assert(Assertion::EndText), None),
+                    ])),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
+        #[test]
+        fn class() {
+            // [^a-c_-]
+            let tokens = vec![
+                Token::LBracket,
+                Token::Negation,
+                Token::Char('a'),
+                Token::Hyphen,
+                Token::Char('c'),
+                Token::Char('_'),
+                Token::Hyphen,
+                Token::RBracket,
+            ];
+            let expected = wrap_regexp(
+                wrap_alt(
+                    Some(wrap_concat(
+                        wrap_factor(Base::Class(Class {
+                            set: CharSet::new([('a', 'c'), ('_', '_'), ('-', '-')]),
+                            negated: true,
+                        }), None),
+                        None
+                    )),
+                    None
+                )
+            );
+            let actual = LL0Parser::new().parse(&tokens);
+            assert_eq!(actual, Ok(expected));
+        }
+
         #[test]
         fn complexed_test() {
             // a(bc|(def|ghi*)j*)*|kl.*mn*
@@ -417,7 +1403,8 @@ mod test {
                     c.to_string(),
                     Factor{
                         val: Base::Char(Token::Char(c)),
-                        q: None
+                        q: None,
+                        greediness: Greediness::Greedy,
                     }
                 )
             })
@@ -434,6 +1421,7 @@ mod test {
                     Factor{
                         val: Base::Char(tuple.0),
                         q: Some(tuple.1),
+                        greediness: Greediness::Greedy,
                     }
                 )
             })
@@ -464,7 +1452,7 @@ mod test {
             let right_alt_in_left_group = Alt{
                 val: Some(
                     Concat{
-                        val: Factor { val: Base::Alt(Box::new(innermost)), q: None },
+                        val: Factor { val: Base::Capture(2, None, Box::new(innermost)), q: None, greediness: Greediness::Greedy },
                         tail: Some(Box::new(concats.remove(1)))
                     },
                 ),
@@ -483,12 +1471,13 @@ mod test {
             let alt = Alt{
                 val: Some(
                     Concat{
-                        val: Factor { val: Base::Char(Token::Char('a')), q: None },
+                        val: Factor { val: Base::Char(Token::Char('a')), q: None, greediness: Greediness::Greedy },
                         tail: Some(
                             Box::new(Concat{
                                 val: Factor { 
-                                    val: Base::Alt(Box::new(left_group)), 
+                                    val: Base::Capture(1, None, Box::new(left_group)), 
                                     q: Some(Token::Quantifier('*')),
+                                    greediness: Greediness::Greedy,
                                 },
                                 tail: None,
                             })
@@ -498,7 +1487,7 @@ mod test {
                 tail: Some(Box::new(Alt { val: Some(concats.remove(0)), tail: None })),
             };
 
-            let expected = Regexp{val: alt};
+            let expected = Regexp{val: alt, captures: vec![None, None, None]};
             let actual = LL0Parser::new().parse(&tokens);
             assert_eq!(actual, Ok(expected));
         }
@@ -0,0 +1,866 @@
+use std::{fmt::{Debug, Display}, ops::ControlFlow, sync::Arc};
+
+use crate::{
+    error::Error,
+    lexer::{get_tokens, get_verbose_tokens},
+    matcher::{
+        executor::backtracker::{BackTracer, DEFAULT_RECURSION_LIMIT},
+        nfa::builder::{thompson::{ThompsonWayBuilder, DEFAULT_SIZE_LIMIT}, NFABuilder},
+        CalloutInfo, CalloutResult, Captures, Match, Matcher, Matches,
+    },
+    parser::{ll0_parser::LL0Parser, Parser},
+};
+
+// compiles a pattern through lexer -> parser -> builder -> executor in one call
+pub struct Regex {
+    pattern: String,
+    matcher: BackTracer,
+    anchored: bool,
+}
+
+// configures how a pattern is compiled
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    size_limit: usize,
+    recursion_limit: usize,
+    unicode_word: bool,
+    dot_all: bool,
+    case_insensitive: bool,
+    multi_line: bool,
+    crlf: bool,
+    verbose: bool,
+    anchored: bool,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            size_limit: DEFAULT_SIZE_LIMIT,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
+            unicode_word: false,
+            dot_all: false,
+            case_insensitive: false,
+            multi_line: false,
+            crlf: false,
+            verbose: false,
+            anchored: false,
+        }
+    }
+
+    // the maximum number of automaton states, compiling fails beyond it
+    pub fn size_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.size_limit = limit;
+        self
+    }
+
+    // the maximum depth of nested "(?R)", "(?1)" and "(?&name)" calls,
+    // a call beyond it fails to match instead of running out of stack
+    pub fn recursion_limit(&mut self, limit: usize) -> &mut RegexBuilder {
+        self.recursion_limit = limit;
+        self
+    }
+
+    // when set, "\b" and "\B" treat any alphanumeric character as a word character,
+    // otherwise only [0-9A-Za-z_] are
+    pub fn unicode_word(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.unicode_word = yes;
+        self
+    }
+
+    // when set, "." matches "\n" as well
+    pub fn dot_all(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.dot_all = yes;
+        self
+    }
+
+    // when set, letters match regardless of their case, the same as "(?i)" at the start
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    // when set, "^" and "$" also match right after and before "\n", the same as "(?m)" at the start
+    pub fn multi_line(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.multi_line = yes;
+        self
+    }
+
+    // when set, "\r\n" is a line terminator in multi-line mode as well,
+    // so "^" and "$" also match after and before "\r" but never between "\r" and "\n"
+    pub fn crlf(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.crlf = yes;
+        self
+    }
+
+    // when set, whitespace and "#" comments in the pattern are ignored, the same as "(?x)" at the start
+    pub fn verbose(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.verbose = yes;
+        self
+    }
+
+    // when set, every search only matches at the position it starts from,
+    // e.g. find tries the beginning of the haystack and nothing else
+    pub fn anchored(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.anchored = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, Error> {
+        let tokens = if self.verbose {
+            get_verbose_tokens(&self.pattern)?
+        } else {
+            get_tokens(&self.pattern)?
+        };
+        let ast = LL0Parser::new().parse(&tokens)?;
+        let nfa = ThompsonWayBuilder::new()
+            .size_limit(self.size_limit)
+            .unicode_word(self.unicode_word)
+            .dot_all(self.dot_all)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .crlf(self.crlf)
+            .exec(ast)?;
+        Ok(Regex {
+            pattern: self.pattern.clone(),
+            matcher: BackTracer::new(nfa).recursion_limit(self.recursion_limit),
+            anchored: self.anchored,
+        })
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn exec(&self, input: &str) -> Vec<String> {
+        Matcher::exec(self, input)
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        Matcher::is_match(self, haystack)
+    }
+
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        Matcher::find(self, haystack)
+    }
+
+    pub fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        Matcher::find_at(self, haystack, start)
+    }
+
+    // the match which begins exactly at `start`, without scanning forward
+    pub fn match_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        Matcher::match_at(self, haystack, start)
+    }
+
+    // the match which spans the whole haystack, as needed for validation
+    pub fn full_match<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        Matcher::full_match(self, haystack)
+    }
+
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h, Regex> {
+        Matches::new(self, haystack)
+    }
+
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        Matcher::captures(self, haystack)
+    }
+
+    pub fn captures_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Captures<'h>> {
+        Matcher::captures_at(self, haystack, start)
+    }
+
+    // the same as captures, but callout is called each time the match reaches "(?C)",
+    // and decides whether it goes on, backtracks or gives up the whole search
+    pub fn captures_with_callout<'h, F>(&self, haystack: &'h str, mut callout: F) -> Option<Captures<'h>>
+    where
+        F: FnMut(CalloutInfo) -> CalloutResult,
+    {
+        for begin in Matcher::starts(self, haystack, 0) {
+            match self.matcher.slots_with_callout(haystack, begin, None, &mut callout) {
+                ControlFlow::Continue(Some(slots)) => return Some(Captures::new(haystack, slots, self.capture_names())),
+                ControlFlow::Continue(None) => (),
+                ControlFlow::Break(()) => return None,
+            }
+        }
+        None
+    }
+
+    // the number of capture groups, including the implicit group 0
+    pub fn captures_len(&self) -> usize {
+        self.matcher.capture_names().len()
+    }
+
+    pub fn capture_names(&self) -> Arc<[Option<String>]> {
+        self.matcher.capture_names()
+    }
+}
+
+impl Matcher for Regex {
+    fn slots_at(&self, haystack: &str, start: usize, end: Option<usize>) -> Option<Vec<Option<usize>>> {
+        self.matcher.slots_at(haystack, start, end)
+    }
+
+    fn capture_names(&self) -> Arc<[Option<String>]> {
+        self.matcher.capture_names()
+    }
+
+    fn is_anchored(&self) -> bool {
+        self.anchored
+    }
+}
+
+impl Debug for Regex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Regex").field(&self.pattern).finish()
+    }
+}
+
+impl Display for Regex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::{escape, lexer::Token, matcher::{nfa::builder::BuildNFAError, CalloutResult}, parser::ParseRegexpError, Error, Regex, RegexBuilder};
+
+    #[test]
+    fn compile_and_exec() {
+        let re = Regex::new("a(b|c)*").unwrap();
+        assert_eq!(re.as_str(), "a(b|c)*");
+        assert_eq!(re.exec("xabcbxac"), vec!["abcb", "ac"]);
+        assert_eq!(re.exec("xyz"), Vec::<String>::new());
+
+        let re = Regex::new("").unwrap();
+        assert_eq!(re.exec("a"), vec!["", ""]);
+    }
+
+    #[test]
+    fn find() {
+        let re = Regex::new("ab*").unwrap();
+        let haystack = "xxabbbyab";
+
+        let m = re.find(haystack).unwrap();
+        assert_eq!((m.start(), m.end()), (2, 6));
+        assert_eq!(m.range(), 2..6);
+        assert_eq!(m.as_str(), "abbb");
+
+        let m = re.find_at(haystack, 3).unwrap();
+        assert_eq!(m.range(), 7..9);
+        assert_eq!(re.find_at(haystack, 8), None);
+        assert_eq!(re.find("xyz"), None);
+
+        assert!(re.is_match(haystack));
+        assert!(!re.is_match("bbb"));
+    }
+
+    #[test]
+    fn find_iter() {
+        let re = Regex::new("ab*").unwrap();
+        let haystack = "xxabbbyab";
+        let spans = re.find_iter(haystack).map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![2..6, 7..9]);
+
+        let re = Regex::new("a*").unwrap();
+        let spans = re.find_iter("baab").map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![0..0, 1..3, 3..3, 4..4]);
+
+        // lazy: stops as soon as the caller does
+        let re = Regex::new("a").unwrap();
+        let mut iter = re.find_iter("aaa");
+        assert_eq!(iter.next().map(|m| m.start()), Some(0));
+        assert_eq!(iter.next().map(|m| m.start()), Some(1));
+    }
+
+    #[test]
+    fn multibyte() {
+        let haystack = "価格は😀100円、🍣は200円";
+        let re = Regex::new("[0-9]+円").unwrap();
+        let spans = re.find_iter(haystack).map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![13..19, 29..35]);
+        assert_eq!(re.exec(haystack), vec!["100円", "200円"]);
+
+        // positions are byte offsets, a search never starts inside a character
+        assert_eq!(re.find_at(haystack, 14).map(|m| m.as_str()), Some("00円"));
+        assert_eq!(re.find_at(haystack, 10).map(|m| m.range()), Some(13..19));
+        assert_eq!(re.match_at(haystack, 10), None);
+
+        // empty matches step over whole characters
+        let re = Regex::new("").unwrap();
+        let spans = re.find_iter("日本").map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![0..0, 3..3, 6..6]);
+
+        let caps = Regex::new("(?<name>[^ ]+) (😀+)").unwrap().captures("名前 😀😀!").unwrap();
+        assert_eq!(caps.name("name").unwrap().range(), 0..6);
+        assert_eq!(caps.get(2).unwrap().as_str(), "😀😀");
+        assert!(Regex::new(r"\b😀").unwrap().find("a😀").is_some());
+        assert_eq!(Regex::new("é$").unwrap().full_match("é").map(|m| m.range()), Some(0..2));
+    }
+
+    #[test]
+    fn captures() {
+        let re = Regex::new("(?<year>(0|1|2)*)-((?:0|1)*)").unwrap();
+        assert_eq!(re.captures_len(), 4);
+        assert_eq!(&*re.capture_names(), &[None, Some("year".to_string()), None, None]);
+
+        let caps = re.captures("x2012-0110").unwrap();
+        assert_eq!(caps.len(), 4);
+        assert_eq!(caps.get(0).unwrap().range(), 1..10);
+        assert_eq!(caps.get(1).unwrap().as_str(), "2012");
+        assert_eq!(caps.name("year").unwrap().as_str(), "2012");
+        assert_eq!(caps.get(2).unwrap().as_str(), "2");
+        assert_eq!(caps.get(3).unwrap().as_str(), "0110");
+        assert_eq!(caps.get(4), None);
+        assert_eq!(caps.name("month"), None);
+        let groups = caps.iter().map(|m| m.map(|m| m.as_str())).collect::<Vec<_>>();
+        assert_eq!(groups, vec![Some("2012-0110"), Some("2012"), Some("2"), Some("0110")]);
+
+        // a group which does not take part in the match
+        let re = Regex::new("(a)|(b)").unwrap();
+        let caps = re.captures("b").unwrap();
+        assert_eq!(caps.get(1), None);
+        assert_eq!(caps.get(2).unwrap().range(), 0..1);
+
+        // the last iteration of a repeated group wins
+        let re = Regex::new("(a|b)*").unwrap();
+        let caps = re.captures("abb").unwrap();
+        assert_eq!(caps.get(1).unwrap().range(), 2..3);
+
+        assert_eq!(re.captures_at("abb", 1).unwrap().get(0).unwrap().range(), 1..3);
+        assert_eq!(Regex::new("a").unwrap().captures("b"), None);
+    }
+
+    #[test]
+    fn perl_class() {
+        let re = Regex::new(r"\d{3}-\d{4}").unwrap();
+        assert_eq!(re.exec("call 555-1234 or 55-12345"), vec!["555-1234"]);
+
+        let re = Regex::new(r"[\w.-]+@\w+").unwrap();
+        assert_eq!(re.exec("mail first.last-name@example now"), vec!["first.last-name@example"]);
+
+        let re = Regex::new(r"\s*\S+").unwrap();
+        assert_eq!(re.exec("ab \t cd"), vec!["ab", " \t cd"]);
+
+        let re = Regex::new(r"[^\D]\W").unwrap();
+        assert_eq!(re.exec("a1 2_3!"), vec!["1 ", "3!"]);
+    }
+
+    #[test]
+    fn dot() {
+        let re = Regex::new("a.c").unwrap();
+        assert_eq!(re.exec("a~c a\tc aあc a😀c"), vec!["a~c", "a\tc", "aあc", "a😀c"]);
+        assert!(!re.is_match("a\nc"));
+
+        let re = Regex::new(".+").unwrap();
+        assert_eq!(re.exec("ab\ncd\n"), vec!["ab", "cd"]);
+
+        let re = RegexBuilder::new("a.c").dot_all(true).build().unwrap();
+        assert!(re.is_match("a\nc"));
+        let re = RegexBuilder::new(".+").dot_all(true).build().unwrap();
+        assert_eq!(re.exec("ab\ncd\n"), vec!["ab\ncd\n"]);
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let re = RegexBuilder::new("error").case_insensitive(true).build().unwrap();
+        assert_eq!(re.exec("Error ERROR error eRRoR erro"), vec!["Error", "ERROR", "error", "eRRoR"]);
+
+        let re = Regex::new("(?i)[a-c]+").unwrap();
+        assert_eq!(re.exec("aBcX AbCd"), vec!["aBc", "AbC"]);
+
+        // folded before the negation
+        let re = Regex::new("(?i)[^x]").unwrap();
+        assert_eq!(re.exec("xXy"), vec!["y"]);
+
+        let re = Regex::new("(?i)straße|σ").unwrap();
+        assert_eq!(re.exec("STRAßE Σ σ"), vec!["STRAßE", "Σ", "σ"]);
+
+        // every character of a fold class matches every other one
+        let re = Regex::new("(?i)k").unwrap();
+        assert_eq!(re.exec("k K \u{212A}"), vec!["k", "K", "\u{212A}"]);
+        let re = Regex::new("(?i)\u{212A}").unwrap();
+        assert_eq!(re.exec("k K"), vec!["k", "K"]);
+        let re = Regex::new("(?i)Σ").unwrap();
+        assert_eq!(re.exec("σ ς"), vec!["σ", "ς"]);
+        let re = Regex::new(r"(?i)(ς)\1").unwrap();
+        assert_eq!(re.exec("ςΣ σς"), vec!["ςΣ", "σς"]);
+        let re = Regex::new(r"(?i)(k)\1").unwrap();
+        assert_eq!(re.exec("k\u{212A}"), vec!["k\u{212A}"]);
+
+        // only the rest of the enclosing group is affected
+        let re = Regex::new("a(?:b(?i)c|d)e").unwrap();
+        assert_eq!(re.exec("abCe aDe adE"), vec!["abCe", "aDe"]);
+    }
+
+    #[test]
+    fn flag_groups() {
+        let re = Regex::new("a(?i:b)c").unwrap();
+        assert_eq!(re.exec("abc aBc aBC"), vec!["abc", "aBc"]);
+
+        // the later alternative is in the scope of "(?-i)" as well
+        let re = RegexBuilder::new("a(?-i)b|c").case_insensitive(true).build().unwrap();
+        assert_eq!(re.exec("Ab AB C c"), vec!["Ab", "c"]);
+
+        let re = Regex::new("(?s)a.(?-s:.)").unwrap();
+        assert_eq!(re.exec("a\nb a\n\n"), vec!["a\nb"]);
+
+        let re = Regex::new("(?m)^x$").unwrap();
+        assert_eq!(re.exec("x\ny\nx"), vec!["x", "x"]);
+        let re = Regex::new("(?m:^x)|\\Ay").unwrap();
+        assert_eq!(re.exec("y\nx\ny"), vec!["y", "x"]);
+        assert!(!Regex::new("^x").unwrap().is_match("y\nx"));
+
+        let re = Regex::new("(?x) \\d+ - (?-x: \\d )").unwrap();
+        assert_eq!(re.exec("12- 3 12-3"), vec!["12- 3 "]);
+    }
+
+    #[test]
+    fn verbose() {
+        let re = RegexBuilder::new(r"
+            (?<year>  \d{4} )  -  # year
+            (?<month> \d{2} )     # month
+            (?#no day)
+        ").verbose(true).build().unwrap();
+        let caps = re.captures("on 2024-05-17").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "2024-05");
+        assert_eq!(caps.name("month").unwrap().as_str(), "05");
+
+        let re = Regex::new(r"(?x) a \  b [ ]c # comment").unwrap();
+        assert_eq!(re.exec("a b c ab c"), vec!["a b c"]);
+    }
+
+    #[test]
+    fn anchors() {
+        let re = Regex::new("^ab|b$").unwrap();
+        assert_eq!(re.exec("abab"), vec!["ab", "b"]);
+        assert_eq!(re.exec("cabc"), Vec::<String>::new());
+
+        let re = Regex::new(r"\Aa*\z").unwrap();
+        assert!(re.is_match("aaa"));
+        assert!(re.is_match(""));
+        assert!(!re.is_match("aab"));
+
+        // an assertion can be repeated or alternated like any other base
+        let re = Regex::new("(^|b)a").unwrap();
+        assert_eq!(re.exec("aba ca"), vec!["a", "ba"]);
+    }
+
+    #[test]
+    fn word_boundary() {
+        let re = Regex::new(r"\berror\b").unwrap();
+        assert_eq!(re.find("an error: errors").map(|m| m.range()), Some(3..8));
+        assert!(!re.is_match("errors_and_warnings"));
+        assert!(re.is_match("error"));
+
+        let re = Regex::new(r"\Bb\B").unwrap();
+        assert_eq!(re.find("b abc").map(|m| m.range()), Some(3..4));
+
+        // ASCII only by default
+        let re = Regex::new(r"\bx").unwrap();
+        assert!(re.is_match("éx"));
+        let re = RegexBuilder::new(r"\bx").unicode_word(true).build().unwrap();
+        assert!(!re.is_match("éx"));
+        assert!(re.is_match("é x"));
+    }
+
+    #[test]
+    fn multi_line() {
+        let re = RegexBuilder::new("^\\w+$").multi_line(true).build().unwrap();
+        assert_eq!(re.exec("error\nwarn info\n\ndebug"), vec!["error", "debug"]);
+
+        // "\A" and "\z" still stand for the whole input
+        let re = RegexBuilder::new("\\A\\w+|\\w+\\z").multi_line(true).build().unwrap();
+        assert_eq!(re.exec("a\nb\nc"), vec!["a", "c"]);
+
+        let re = RegexBuilder::new("^$").multi_line(true).build().unwrap();
+        let spans = re.find_iter("a\n\nb\n").map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![2..2, 5..5]);
+
+        // without crlf, "\r" is part of the line
+        let re = RegexBuilder::new("^\\w+$").multi_line(true).build().unwrap();
+        assert_eq!(re.exec("ab\r\ncd\r\n"), Vec::<String>::new());
+
+        let re = RegexBuilder::new("^\\w+$").multi_line(true).crlf(true).build().unwrap();
+        assert_eq!(re.exec("ab\r\ncd\r\nef"), vec!["ab", "cd", "ef"]);
+
+        // an empty line between "\r" and "\n" is never matched
+        let re = RegexBuilder::new("^$").multi_line(true).crlf(true).build().unwrap();
+        let spans = re.find_iter("a\r\n\r\nb").map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![3..3]);
+    }
+
+    #[test]
+    fn lookahead() {
+        // a password of at least six characters containing a digit and a letter
+        let re = Regex::new(r"^(?=.*\d)(?=.*[a-z]).{6,}$").unwrap();
+        assert!(re.is_match("secret1"));
+        assert!(!re.is_match("secret"));
+        assert!(!re.is_match("123456"));
+        assert!(!re.is_match("abc1"));
+
+        let re = Regex::new("foo(?!bar)").unwrap();
+        let spans = re.find_iter("foobar foobaz foo").map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(spans, vec![7..10, 14..17]);
+
+        // the lookahead does not consume, so "\w+" covers the same text
+        let re = Regex::new(r"(?=(\w+)x)\w+").unwrap();
+        let caps = re.captures("abx").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "abx");
+        assert_eq!(caps.get(1).unwrap().as_str(), "ab");
+
+        // a negative lookahead never keeps captures
+        let re = Regex::new("(?!(a)b)(a)").unwrap();
+        let caps = re.captures("ab ac").unwrap();
+        assert_eq!(caps.get(0).unwrap().range(), 3..4);
+        assert_eq!(caps.get(1), None);
+
+        // each copy of a repetition has its own sub-automaton
+        let re = Regex::new("(?:(?=[a-c])\\w){2}").unwrap();
+        assert_eq!(re.exec("ad bc ca"), vec!["bc", "ca"]);
+    }
+
+    #[test]
+    fn lookbehind() {
+        let re = Regex::new(r"(?<=\$)\d+").unwrap();
+        assert_eq!(re.exec("$10 and 20 and $300"), vec!["10", "300"]);
+
+        let re = Regex::new(r"\b(?<!-)\d+").unwrap();
+        assert_eq!(re.exec("-1 2 -3 4"), vec!["2", "4"]);
+
+        // the length of a lookbehind is not bounded
+        let re = Regex::new(r"(?<=^\w+: .*)error").unwrap();
+        assert_eq!(re.find("db: connection error").map(|m| m.range()), Some(15..20));
+        assert!(!re.is_match("error: db"));
+
+        let re = Regex::new("(?<=ab|c{2,}|^)x").unwrap();
+        assert_eq!(re.exec("x abx bx ccx cx"), vec!["x", "x", "x"]);
+        assert_eq!(re.find_iter("x abx bx ccx cx").map(|m| m.start()).collect::<Vec<_>>(), vec![0, 4, 11]);
+
+        // captures inside a lookbehind get their usual spans
+        let re = Regex::new(r"(?<=(?<key>\w+)=)\w+").unwrap();
+        let caps = re.captures("a=1").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "1");
+        assert_eq!(caps.name("key").unwrap().as_str(), "a");
+
+        // a group captured by an earlier lookbehind is complete before a later one refers to it
+        let re = Regex::new(r"(?<=(\w))(?<=\1\1)x").unwrap();
+        assert_eq!(re.find("abx aax").map(|m| m.range()), Some(6..7));
+
+        // lookarounds nest in either direction
+        let re = Regex::new("(?<=(?=ab)a)b").unwrap();
+        assert_eq!(re.find("xab").map(|m| m.range()), Some(2..3));
+        let re = Regex::new("(?<=😀)é").unwrap();
+        assert_eq!(re.find("é😀é").map(|m| m.range()), Some(6..8));
+    }
+
+    #[test]
+    fn backref() {
+        let re = Regex::new(r#"(["']).*?\1"#).unwrap();
+        assert_eq!(re.exec(r#"say "it's" and 'a "b"' now"#), vec![r#""it's""#, r#"'a "b"'"#]);
+
+        let re = Regex::new(r"(?<word>\w+) \k<word>\b").unwrap();
+        assert_eq!(re.find("is this this thing").map(|m| m.as_str()), Some("this this"));
+        assert!(!re.is_match("this thistle"));
+
+        // a group which has not matched matches nothing
+        let re = Regex::new(r"(a)?\1b").unwrap();
+        assert!(!re.is_match("b"));
+
+        // the captured text, not the pattern, is matched
+        let re = Regex::new(r"(\d)\1").unwrap();
+        assert_eq!(re.exec("12 33 4"), vec!["33"]);
+
+        let re = Regex::new(r"(?i)(ab)\1").unwrap();
+        assert_eq!(re.exec("abAB aBab"), vec!["abAB", "aBab"]);
+
+        let re = Regex::new(r"(é+)-\1").unwrap();
+        assert_eq!(re.exec("éé-éé é-x"), vec!["éé-éé"]);
+
+        // inside the group itself it refers to the span of the former time through
+        let re = Regex::new(r"(?:(a\1?)b)+").unwrap();
+        assert_eq!(re.find("ababx").map(|m| m.range()), Some(0..4));
+        let re = Regex::new(r"(?:(a|b\1)c)+").unwrap();
+        assert_eq!(re.find("acbac").map(|m| m.as_str()), Some("acbac"));
+        let re = Regex::new(r"(a\1?b)-(?1)").unwrap();
+        assert_eq!(re.find("ab-ab").map(|m| m.range()), Some(0..5));
+        // and a call sees the span of the group outside it
+        assert_eq!(re.find("ab-aabb").map(|m| m.range()), Some(0..7));
+
+        // a backreference inside a lookbehind reads leftward as well
+        let re = Regex::new(r"(\w)(?<=\1\1)").unwrap();
+        assert_eq!(re.find("abccd").map(|m| m.range()), Some(3..4));
+
+        // but not to a group of the same lookbehind, which the reversed match captures later
+        let err = Regex::new(r"(?<=(\w)\1)x").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::unsupported_in_lookbehind(Token::Backref(1), 4)));
+        assert_eq!(err.to_string(), "backreference at 4 is unsupported in lookbehind");
+        let err = Regex::new(r"(?<=(?<k>\w)\k<k>)x").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::unsupported_in_lookbehind(Token::NamedBackref("k".to_string()), 4)));
+
+        assert!(Regex::new(r"\2(a)").is_err());
+    }
+
+    #[test]
+    fn atomic() {
+        // fails at once instead of trying every split of the digits
+        let re = Regex::new(r"^(?>\d+)+x").unwrap();
+        assert!(!re.is_match("12345678901234567890123456789y"));
+        let re = Regex::new(r"^\d++x").unwrap();
+        assert!(re.is_match("123x"));
+
+        // a lookbehind runs reversed, where committing would pick other ways than the pattern read forward
+        let err = Regex::new("(?<=(?>a|ab)c)x").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::unsupported_in_lookbehind(Token::Atomic, 1)));
+        assert_eq!(err.to_string(), "atomic group at 1 is unsupported in lookbehind");
+        let err = Regex::new("(?<=a{1,2}+)x").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::unsupported_in_lookbehind(Token::Quantifier('+'), 3)));
+        assert_eq!(err.to_string(), "possessive quantifier at 3 is unsupported in lookbehind");
+        assert!(Regex::new("(?<=a)(?>b|bc)").is_ok());
+
+        // captures inside an atomic group are kept
+        let re = Regex::new(r"(?>(\w+)-)(\d)").unwrap();
+        let caps = re.captures("key-1").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "key");
+        assert_eq!(caps.get(2).unwrap().as_str(), "1");
+
+        // and dropped when the match goes on elsewhere
+        let re = Regex::new(r"(?>(a))b|(a)c").unwrap();
+        let caps = re.captures("ac").unwrap();
+        assert_eq!(caps.get(1), None);
+        assert_eq!(caps.get(2).unwrap().as_str(), "a");
+    }
+
+    #[test]
+    fn recursion() {
+        let re = Regex::new(r"^(?<v>\[(?:(?&v)(?:,(?&v))*)?\]|\d+)$").unwrap();
+        assert!(re.is_match("[1,[2,[]],[[3]]]"));
+        assert!(!re.is_match("[1,[2]"));
+        assert!(!re.is_match("[1,]"));
+
+        // captures made inside a call are dropped when it returns
+        let re = Regex::new(r"(\w)(?:-(?1))+").unwrap();
+        let caps = re.captures("a-b-c").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "a-b-c");
+        assert_eq!(caps.get(1).unwrap().as_str(), "a");
+
+        let re = RegexBuilder::new(r"^(\((?1)*\))$").recursion_limit(2).build().unwrap();
+        assert!(re.is_match("((()))"));
+        assert!(!re.is_match("(((())))"));
+
+        // left recursion ends at the limit instead of looping forever
+        let re = Regex::new("(?R)?a").unwrap();
+        assert_eq!(re.find("aa").map(|m| m.range()), Some(0..2));
+
+        // a group can be called before it is opened
+        let re = Regex::new("(?2)(a)(b)").unwrap();
+        let caps = re.captures("bab").unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "bab");
+        assert_eq!(caps.get(1).unwrap().as_str(), "a");
+        let re = Regex::new(r"(?&d)-(?<d>\d+)").unwrap();
+        assert_eq!(re.find("x 12-345").map(|m| m.range()), Some(2..8));
+
+        assert!(Regex::new("(?3)(a)(b)").is_err());
+        assert!(Regex::new("(?&x)(?<y>a)").is_err());
+        assert!(Regex::new("(?1)(?<=(a))").is_err());
+        let err = Regex::new("(?<=(?R))a").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::unsupported_in_lookbehind(Token::Recurse(0), 1)));
+    }
+
+    #[test]
+    fn conditional() {
+        let re = Regex::new(r"^(?<open><)?\w+@\w+(?(<open>)>)$").unwrap();
+        assert!(re.is_match("<me@host>"));
+        assert!(re.is_match("me@host"));
+        assert!(!re.is_match("<me@host"));
+        assert!(!re.is_match("me@host>"));
+
+        // a group which matched in an alternative given up does not count
+        let re = Regex::new("^(?:(a)x|ay)(?(1)1|2)$").unwrap();
+        assert!(re.is_match("ax1"));
+        assert!(re.is_match("ay2"));
+        assert!(!re.is_match("ay1"));
+
+        // a lookbehind would check the condition before it captures the group
+        let err = Regex::new("(?<=(a)(?(1)b|c))x").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::unsupported_in_lookbehind(Token::Conditional(1), 4)));
+        assert_eq!(err.to_string(), "conditional group at 4 is unsupported in lookbehind");
+        let re = Regex::new("(a)?(?<=(?(1)a|b))x").unwrap();
+        assert!(re.is_match("ax"));
+        assert!(re.is_match("bx"));
+
+        assert!(Regex::new("(?(1)a)(b)").is_err());
+        assert!(Regex::new("(a)(?(1)b|c|d)").is_err());
+    }
+
+    #[test]
+    fn callout() {
+        let re = Regex::new(r"(\d+)(?C1)-(?C2)").unwrap();
+        let mut seen = vec![];
+        let caps = re.captures_with_callout("a12-", |info| {
+            seen.push((info.number, info.position, info.slots[2..4].to_vec()));
+            CalloutResult::Continue
+        }).unwrap();
+        assert_eq!(caps.get(0).unwrap().range(), 1..4);
+        assert_eq!(seen, vec![(1, 3, vec![Some(1), Some(3)]), (2, 4, vec![Some(1), Some(3)])]);
+
+        // failing makes the match backtrack to a shorter number
+        let caps = re.captures_with_callout("123-", |info| {
+            if info.number == 1 && info.position == 3 { CalloutResult::Fail } else { CalloutResult::Continue }
+        });
+        assert_eq!(caps, None);
+        let re = Regex::new(r"(\d+)(?C)").unwrap();
+        let caps = re.captures_with_callout("123", |info| {
+            if info.position > 2 { CalloutResult::Fail } else { CalloutResult::Continue }
+        }).unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "12");
+
+        // aborting tries no other start position
+        let mut calls = 0;
+        let caps = re.captures_with_callout("12", |_| {
+            calls += 1;
+            CalloutResult::Abort
+        });
+        assert_eq!(caps, None);
+        assert_eq!(calls, 1);
+
+        // searches without a callback go through callouts
+        assert_eq!(re.find("x9").map(|m| m.range()), Some(1..2));
+    }
+
+    #[test]
+    fn quote() {
+        let re = Regex::new(r"^\Q(a|b)*\E+$").unwrap();
+        assert!(re.is_match("(a|b)*"));
+        assert!(re.is_match("(a|b)**"));
+        assert!(!re.is_match("a"));
+
+        let re = Regex::new(r"[\Q]^\E]+").unwrap();
+        assert_eq!(re.find("ab]^]c").map(|m| m.as_str()), Some("]^]"));
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let all_ascii = (' '..='~').collect::<String>();
+        let texts = [
+            "", "a.b", "1+1=2?", "(x|y)*", "[^a-z]{2,}$", "\\d \\Q\\E", "# not a comment",
+            "tab\tnew\nline\r\u{3000}", "日本語 😀", &all_ascii,
+        ];
+        for text in texts {
+            let pattern = escape(text);
+            for verbose in [false, true] {
+                let re = RegexBuilder::new(&pattern).verbose(verbose).build().unwrap();
+                assert!(re.full_match(text).is_some(), "{:?} does not match {:?}", pattern, text);
+                // matches nothing but the text itself
+                let haystack = format!("{}x{}", text, text);
+                let found = re.find_iter(&haystack).map(|m| m.as_str()).collect::<Vec<_>>();
+                if !text.is_empty() {
+                    assert_eq!(found, vec![text, text], "{:?}", pattern);
+                }
+                assert!(re.full_match(&haystack).is_none());
+            }
+        }
+
+        let re = Regex::new(&format!("[{}]+", escape("^]-\\"))).unwrap();
+        assert_eq!(re.find("a]\\-^b").map(|m| m.as_str()), Some("]\\-^"));
+
+        // a long text compiles in linear time, without a call per character
+        let text = "a.b*(c)[d]\\ é".chars().cycle().take(20_000).collect::<String>();
+        let started = Instant::now();
+        let re = Regex::new(&escape(&text)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let haystack = format!("xx{}yy", text);
+        assert_eq!(re.find(&haystack).map(|m| m.as_str()), Some(text.as_str()));
+    }
+
+    #[test]
+    fn class_set_operation() {
+        let re = Regex::new("[a-z--[aeiou]]+").unwrap();
+        assert_eq!(re.find_iter("strength queue").map(|m| m.as_str()).collect::<Vec<_>>(), vec!["str", "ngth", "q"]);
+
+        let re = Regex::new(r"^[\w&&[^_]--[l1O0]]+$").unwrap();
+        assert!(re.is_match("abcXYZ789"));
+        assert!(!re.is_match("a_b"));
+        assert!(!re.is_match("hello"));
+        assert!(!re.is_match("O0"));
+
+        let re = Regex::new("[[a-c]~~[b-d]]+").unwrap();
+        assert_eq!(re.find("bcad").map(|m| m.as_str()), Some("ad"));
+
+        // case folding applies to the result of the operations
+        let re = RegexBuilder::new("[a-z--[aeiou]]+").case_insensitive(true).build().unwrap();
+        assert_eq!(re.find("AEIOUxYz").map(|m| m.as_str()), Some("xYz"));
+
+        assert!(Regex::new("[a--]").is_err());
+        assert!(Regex::new("[a[b]").is_err());
+    }
+
+    #[test]
+    fn anchored_search() {
+        let re = Regex::new("a|ab").unwrap();
+        assert_eq!(re.match_at("xab", 1).map(|m| m.range()), Some(1..2));
+        assert_eq!(re.match_at("xab", 0), None);
+
+        // prefers the alternative which reaches the end
+        assert_eq!(re.full_match("ab").map(|m| m.range()), Some(0..2));
+        assert_eq!(re.full_match("abb"), None);
+        assert_eq!(re.full_match("xab"), None);
+
+        let re = RegexBuilder::new("[0-9]+").anchored(true).build().unwrap();
+        assert_eq!(re.find("12a3").map(|m| m.range()), Some(0..2));
+        assert_eq!(re.find("a123"), None);
+        assert_eq!(re.find_at("a123", 1).map(|m| m.range()), Some(1..4));
+        assert_eq!(re.exec("12a3"), vec!["12"]);
+        assert_eq!(re.captures("x1"), None);
+    }
+
+    #[test]
+    fn size_limit() {
+        let err = Regex::new("(?:a{1000}){1000}").unwrap_err();
+        assert_eq!(err, Error::Build(BuildNFAError::TooManyStates(100_000)));
+
+        let err = RegexBuilder::new("(a|b){10}").size_limit(50).build().unwrap_err();
+        assert_eq!(err, Error::Build(BuildNFAError::TooManyStates(50)));
+        assert_eq!(err.to_string(), "compiled automaton exceeds the size limit of 50 states");
+
+        let re = RegexBuilder::new("(a|b){3}").size_limit(50).build().unwrap();
+        assert_eq!(re.exec("abba"), vec!["abb"]);
+
+        // a repetition near the limit is built in linear time
+        let started = Instant::now();
+        let re = Regex::new("a{50000}").unwrap();
+        assert!(Regex::new("a{1,20000}").is_ok());
+        assert!(Regex::new(r"\d{1,5000}").is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let haystack = "a".repeat(50_001);
+        assert_eq!(re.find(&haystack).map(|m| m.range()), Some(0..50_000));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let err = Regex::new("a**").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::new(Some(Token::Quantifier('*')), 2)));
+        assert_eq!(err.to_string(), "cannot parse Quantifier('*') at 2");
+
+        let err = Regex::new("(a").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::new(None, 2)));
+        assert_eq!(err.to_string(), "unexpected end of pattern at 2");
+
+        let err = Regex::new("a\\").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::new(None, 2)));
+        assert_eq!(err.to_string(), "unexpected end of pattern at 2");
+
+        let err = Regex::new("(?<a>x)(?<a>y)").unwrap_err();
+        assert_eq!(err, Error::Parse(ParseRegexpError::new(Some(Token::NamedGroup("a".to_string())), 3)));
+    }
+}
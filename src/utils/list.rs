@@ -60,10 +60,9 @@ where
 
 impl<T> List<T> {
     pub fn new(v: Option<T>) -> List<T> {
-        if v.is_none() {
-            List::Nil
-        } else {
-            List::Cons(v.unwrap(), Box::new(List::Nil))
+        match v {
+            None => List::Nil,
+            Some(v) => List::Cons(v, Box::new(List::Nil)),
         }
     }
 
@@ -72,9 +71,9 @@ impl<T> List<T> {
         Self::from_rev(iter)
     }
 
-    pub fn from_rev<I: IntoIterator<Item = T> + Iterator<Item = T>>(mut iter: I) -> List<T> {
+    pub fn from_rev<I: IntoIterator<Item = T> + Iterator<Item = T>>(iter: I) -> List<T> {
         let mut list = List::<T>::new(None);
-        while let Some(v) = iter.next() {
+        for v in iter {
             list = list.prepend(v);
         }
         list